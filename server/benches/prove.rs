@@ -0,0 +1,53 @@
+// Compares proving with the key prepared once at startup against preparing the
+// VRF and round-tripping the secret and seed through strings on every proof.
+//
+// cargo bench --bench prove
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use num::{BigInt, Num};
+use stark_vrf::{BaseField, StarkVRF};
+use starknet::core::types::Felt;
+use starknet::macros::felt;
+use std::str::FromStr;
+
+#[allow(dead_code)]
+#[path = "../src/vrf.rs"]
+mod vrf;
+
+use vrf::{felt_to_base_field, VrfKey};
+
+const SECRET_KEY: Felt = felt!("0x1a4");
+const SEED: Felt = felt!("0x5db4e1c9bd8b0898674bf96f79e8fbffa3fe6d70a4597683c4dba2f0930dc45");
+
+fn prove_prepared(vrf_key: &VrfKey, seed: Felt) {
+    black_box(vrf_key.prove(&[felt_to_base_field(seed)]).unwrap());
+}
+
+fn prove_unprepared(vrf_key: &VrfKey, secret_key: &str, seed: Felt) {
+    let hex = seed.to_hex_string();
+    let seed =
+        BaseField::from_str(&BigInt::from_str_radix(&hex[2..], 16).unwrap().to_string()).unwrap();
+
+    let ecvrf = StarkVRF::new(vrf_key.public_key).unwrap();
+    let secret_key = secret_key.parse().unwrap();
+    let proof = ecvrf.prove(&secret_key, &[seed]).unwrap();
+    black_box(ecvrf.hash_to_sqrt_ratio_hint(&[seed]));
+    black_box(ecvrf.proof_to_hash(&proof).unwrap());
+}
+
+fn bench_prove(c: &mut Criterion) {
+    let vrf_key = VrfKey::from_secret_key(SECRET_KEY);
+    let secret_key = SECRET_KEY.to_biguint().to_string();
+
+    let mut group = c.benchmark_group("prove");
+    group.bench_function("prepared", |b| {
+        b.iter(|| prove_prepared(&vrf_key, black_box(SEED)))
+    });
+    group.bench_function("unprepared", |b| {
+        b.iter(|| prove_unprepared(&vrf_key, &secret_key, black_box(SEED)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_prove);
+criterion_main!(benches);
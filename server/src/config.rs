@@ -0,0 +1,830 @@
+//! Layered server configuration.
+//!
+//! Settings are resolved from, in increasing order of precedence: built-in
+//! defaults, the TOML file passed with `--config`, environment variables and
+//! CLI flags. The resulting [`Config`] is the single source for `AppState`.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context};
+use serde::{Deserialize, Deserializer};
+use starknet::core::chain_id::{MAINNET, SEPOLIA};
+use starknet::core::types::Felt;
+use starknet::core::utils::cairo_short_string_to_felt;
+use starknet::macros::felt;
+use url::Url;
+
+use crate::routes::outside_execution::types::OutsideExecution;
+use crate::state::secrets::SecretOptions;
+use crate::telemetry::OtlpConfig;
+use crate::Args;
+
+pub const DEFAULT_HOST: &str = "0.0.0.0";
+pub const DEFAULT_PORT: u16 = 3000;
+pub const DEFAULT_OUTSIDE_EXECUTION_EXPIRY: u64 = 600;
+pub const DEFAULT_MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+pub const DEFAULT_MAX_PROOF_BATCH_SIZE: usize = 1024;
+pub const DEFAULT_PROVER_QUEUE_SIZE: usize = 64;
+pub const DEFAULT_PROVER_RETRY_AFTER: u64 = 1;
+pub const DEFAULT_READINESS_TIMEOUT: u64 = 5;
+pub const DEFAULT_TRACKING_POLL_INTERVAL: u64 = 5;
+pub const DEFAULT_TRACKING_RETENTION: u64 = 24 * 60 * 60;
+pub const DEFAULT_TRACKING_MAX_BUNDLES: usize = 100_000;
+/// 1 STRK, in fri.
+pub const DEFAULT_RELAYER_MAX_FEE: u128 = 10_u128.pow(18);
+/// STRK, the fee token of Starknet v3 transactions.
+pub const DEFAULT_FEE_TOKEN: Felt =
+    felt!("0x04718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d");
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub vrf: VrfConfig,
+    pub account: AccountConfig,
+    pub chains: BTreeMap<String, ChainConfig>,
+    pub outside_execution: OutsideExecutionConfig,
+    pub prover: ProverConfig,
+    pub telemetry: TelemetryConfig,
+    pub limits: LimitsConfig,
+    pub readiness: ReadinessConfig,
+    pub tracking: TrackingConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    pub runtime: RuntimeFlavor,
+    /// Worker threads of the `multi_thread` runtime, defaults to the number of cores.
+    pub worker_threads: Option<usize>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            host: DEFAULT_HOST.into(),
+            port: DEFAULT_PORT,
+            runtime: RuntimeFlavor::default(),
+            worker_threads: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum RuntimeFlavor {
+    /// Single threaded tokio runtime, proofs still run on the prover pool.
+    #[default]
+    CurrentThread,
+    /// Multi threaded tokio runtime.
+    MultiThread,
+}
+
+/// Blocking pool proofs are computed on.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProverConfig {
+    /// Proofs computed concurrently, defaults to the number of cores.
+    pub workers: Option<usize>,
+    /// Requests waiting for a worker before new ones are rejected with `503`.
+    pub queue_size: usize,
+    /// `Retry-After` returned with `503`, in seconds.
+    pub retry_after: u64,
+}
+
+impl Default for ProverConfig {
+    fn default() -> Self {
+        ProverConfig {
+            workers: None,
+            queue_size: DEFAULT_PROVER_QUEUE_SIZE,
+            retry_after: DEFAULT_PROVER_RETRY_AFTER,
+        }
+    }
+}
+
+impl ProverConfig {
+    pub fn workers(&self) -> usize {
+        self.workers.unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VrfConfig {
+    pub secret_key: SecretOptions,
+    /// Start without checking the VRF accounts store the public key of the secret key.
+    pub skip_public_key_check: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccountConfig {
+    pub address: Option<Felt>,
+    pub private_key: SecretOptions,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChainConfig {
+    /// Chain id, either as a Cairo short string (`SN_MAIN`) or a hex felt.
+    #[serde(deserialize_with = "deserialize_chain_id")]
+    pub chain_id: Felt,
+    /// RPC used when the request does not provide one.
+    pub rpc_url: Option<Url>,
+    /// VRF account for this chain, defaults to `account.address`.
+    pub vrf_account_address: Option<Felt>,
+    /// Private key of the VRF account, defaults to `account.private_key`.
+    #[serde(default)]
+    pub private_key: SecretOptions,
+    /// VRF secret key of this chain's outside executions, defaults to `vrf.secret_key`.
+    /// `/info`, `/proof` and `/verify` always use `vrf.secret_key`.
+    #[serde(default)]
+    pub vrf_secret_key: SecretOptions,
+    /// RPC hosts (`rpc.example.com`, `localhost:5050`) or URL prefixes
+    /// (`https://rpc.example.com/starknet`, matched on whole path segments) clients
+    /// may use for this chain.
+    #[serde(default)]
+    pub allowed_rpc_urls: Vec<String>,
+    /// Executors clients may restrict the VRF account outside execution to.
+    #[serde(default)]
+    pub allowed_executors: Vec<Felt>,
+    /// Funded accounts submitting bundles on `/outside_execution/relay`.
+    #[serde(default)]
+    pub relayers: Vec<RelayerConfig>,
+    /// Highest max fee, in the fee token's base unit, a relayer signs for one relayed
+    /// transaction. Bundles estimated above it are rejected.
+    #[serde(
+        default = "default_relayer_max_fee",
+        deserialize_with = "deserialize_amount"
+    )]
+    pub relayer_max_fee: u128,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RelayerConfig {
+    pub address: Felt,
+    #[serde(default)]
+    pub private_key: SecretOptions,
+}
+
+impl ChainConfig {
+    pub fn new(chain_id: Felt) -> ChainConfig {
+        ChainConfig {
+            chain_id,
+            rpc_url: None,
+            vrf_account_address: None,
+            private_key: SecretOptions::default(),
+            vrf_secret_key: SecretOptions::default(),
+            allowed_rpc_urls: vec![],
+            allowed_executors: vec![],
+            relayers: vec![],
+            relayer_max_fee: DEFAULT_RELAYER_MAX_FEE,
+        }
+    }
+
+    pub fn with_rpc_url(mut self, rpc_url: Url) -> ChainConfig {
+        self.rpc_url = Some(rpc_url);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutsideExecutionConfig {
+    /// Validity window of the signed outside execution, in seconds.
+    pub expiry: u64,
+    /// How the `rpc_url` supplied in the request context is handled.
+    pub client_rpc_url: ClientRpcUrlPolicy,
+    /// Version of the outside execution signed by the VRF account.
+    pub version: OutsideExecutionVersion,
+    /// Pre-flight check of the final transaction before it is returned.
+    pub simulation: SimulationMode,
+    /// How the user's outside execution signature is checked before co-signing.
+    pub signature_validation: SignatureValidation,
+    /// Restrict the outer window to the user's outside execution window.
+    pub clamp_to_inner: bool,
+    /// Clock the outer window starts from.
+    pub clock: Clock,
+}
+
+impl Default for OutsideExecutionConfig {
+    fn default() -> Self {
+        OutsideExecutionConfig {
+            expiry: DEFAULT_OUTSIDE_EXECUTION_EXPIRY,
+            client_rpc_url: ClientRpcUrlPolicy::default(),
+            version: OutsideExecutionVersion::default(),
+            simulation: SimulationMode::default(),
+            signature_validation: SignatureValidation::default(),
+            clamp_to_inner: true,
+            clock: Clock::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Clock {
+    /// The host clock.
+    #[default]
+    System,
+    /// Timestamp of the latest block of the chain, immune to host clock drift.
+    Block,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientRpcUrlPolicy {
+    /// Accept client URLs matching the chain `rpc_url` or `allowed_rpc_urls`.
+    #[default]
+    Allowlist,
+    /// Always use the chain `rpc_url`, client URLs are ignored.
+    Ignore,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum OutsideExecutionVersion {
+    /// `execute_from_outside_v2`.
+    #[default]
+    V2,
+    /// `execute_from_outside_v3`, the VRF account must implement it.
+    V3,
+    /// Same version as the user's outside execution.
+    MatchInner,
+}
+
+impl OutsideExecutionVersion {
+    /// Returns the version to use for the VRF account wrapper of `inner`.
+    pub fn resolve(self, inner: &OutsideExecution) -> OutsideExecutionVersion {
+        match (self, inner) {
+            (OutsideExecutionVersion::MatchInner, OutsideExecution::V2(_)) => {
+                OutsideExecutionVersion::V2
+            }
+            (OutsideExecutionVersion::MatchInner, OutsideExecution::V3(_)) => {
+                OutsideExecutionVersion::V3
+            }
+            (version, _) => version,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum SimulationMode {
+    /// Return the signed outside execution without checking it.
+    #[default]
+    Disabled,
+    /// `starknet_simulateTransactions`, skipping the sender validation.
+    Simulate,
+    /// `starknet_estimateFee`.
+    EstimateFee,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureValidation {
+    /// Call `is_valid_signature` on the user's account.
+    #[default]
+    Rpc,
+    /// Co-sign without checking the user's signature.
+    Disabled,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    /// Enable the OpenTelemetry Protocol (OTLP) trace exporter.
+    pub otlp: bool,
+    /// OTLP collector endpoint.
+    pub otlp_endpoint: Option<String>,
+}
+
+impl TelemetryConfig {
+    pub fn otlp_config(&self) -> Option<OtlpConfig> {
+        self.otlp.then(|| OtlpConfig {
+            endpoint: self.otlp_endpoint.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Maximum size of a request body, in bytes.
+    pub max_body_size: usize,
+    /// Maximum number of seeds in a `/proof/batch` request.
+    pub max_proof_batch_size: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            max_proof_batch_size: DEFAULT_MAX_PROOF_BATCH_SIZE,
+        }
+    }
+}
+
+/// Checks run by `/ready` on every chain.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReadinessConfig {
+    /// Timeout of each RPC call made by a check, in seconds. Also used by the
+    /// startup VRF public key check.
+    pub timeout: u64,
+    /// Token whose balance of the VRF account is checked.
+    pub fee_token: Felt,
+    /// Fee token balance, in the token's base unit, below which the instance is
+    /// reported as low on funds. This check is not critical.
+    #[serde(deserialize_with = "deserialize_amount")]
+    pub min_fee_token_balance: u128,
+}
+
+impl Default for ReadinessConfig {
+    fn default() -> Self {
+        ReadinessConfig {
+            timeout: DEFAULT_READINESS_TIMEOUT,
+            fee_token: DEFAULT_FEE_TOKEN,
+            min_fee_token_balance: 0,
+        }
+    }
+}
+
+/// Tracking of the bundles handed out, served on `/status/{id}`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrackingConfig {
+    /// Seconds between two polls of the pending bundles.
+    pub poll_interval: u64,
+    /// Seconds a landed, reverted or expired bundle stays queryable.
+    pub retention: u64,
+    /// Bundles kept at most, the oldest ones are dropped first.
+    pub max_bundles: usize,
+}
+
+impl Default for TrackingConfig {
+    fn default() -> Self {
+        TrackingConfig {
+            poll_interval: DEFAULT_TRACKING_POLL_INTERVAL,
+            retention: DEFAULT_TRACKING_RETENTION,
+            max_bundles: DEFAULT_TRACKING_MAX_BUNDLES,
+        }
+    }
+}
+
+impl Config {
+    /// Builds the configuration from the optional `--config` file, then applies
+    /// environment and CLI overrides from `args`.
+    pub fn load(args: &Args) -> anyhow::Result<Config> {
+        let mut config = match &args.config {
+            Some(path) => Config::from_file(path)?,
+            None => Config::with_default_chains(),
+        };
+
+        config.apply_args(args)?;
+
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<Config> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        Config::from_toml(&content)
+            .with_context(|| format!("invalid config file {}", path.display()))
+    }
+
+    pub fn from_toml(content: &str) -> anyhow::Result<Config> {
+        let mut config: Config = toml::from_str(content)?;
+
+        if config.chains.is_empty() {
+            config.chains = default_chains();
+        }
+
+        Ok(config)
+    }
+
+    /// Default configuration, serving mainnet and sepolia through the Cartridge RPC.
+    pub fn with_default_chains() -> Config {
+        Config {
+            chains: default_chains(),
+            ..Default::default()
+        }
+    }
+
+    fn apply_args(&mut self, args: &Args) -> anyhow::Result<()> {
+        if let Some(host) = &args.host {
+            self.server.host = host.clone();
+        }
+        if let Some(port) = args.port {
+            self.server.port = port;
+        }
+        if let Some(runtime) = args.runtime {
+            self.server.runtime = runtime;
+        }
+        if let Some(worker_threads) = args.worker_threads {
+            self.server.worker_threads = Some(worker_threads);
+        }
+
+        let vrf_secret_key = args.vrf_secret_key_options();
+        if vrf_secret_key.is_set() {
+            self.vrf.secret_key = vrf_secret_key;
+        }
+        if args.skip_public_key_check {
+            self.vrf.skip_public_key_check = true;
+        }
+
+        if let Some(account_address) = &args.account_address {
+            self.account.address =
+                Some(Felt::from_hex(account_address).context("invalid account address")?);
+        }
+        let account_private_key = args.account_private_key_options();
+        if account_private_key.is_set() {
+            self.account.private_key = account_private_key;
+        }
+
+        for chain in &args.chains {
+            let (chain_id, rpc_url) = parse_chain_arg(chain)?;
+
+            match self
+                .chains
+                .values_mut()
+                .find(|chain| chain.chain_id == chain_id)
+            {
+                Some(existing) => {
+                    if rpc_url.is_some() {
+                        existing.rpc_url = rpc_url;
+                    }
+                }
+                None => {
+                    let mut entry = ChainConfig::new(chain_id);
+                    entry.rpc_url = rpc_url;
+                    self.chains.insert(chain.clone(), entry);
+                }
+            }
+        }
+
+        if let Some(expiry) = args.outside_execution_expiry {
+            self.outside_execution.expiry = expiry;
+        }
+        if let Some(client_rpc_url) = args.client_rpc_url {
+            self.outside_execution.client_rpc_url = client_rpc_url;
+        }
+        if let Some(version) = args.outside_execution_version {
+            self.outside_execution.version = version;
+        }
+        if let Some(simulation) = args.outside_execution_simulation {
+            self.outside_execution.simulation = simulation;
+        }
+        if let Some(signature_validation) = args.outside_execution_signature_validation {
+            self.outside_execution.signature_validation = signature_validation;
+        }
+        if let Some(clamp_to_inner) = args.outside_execution_clamp_to_inner {
+            self.outside_execution.clamp_to_inner = clamp_to_inner;
+        }
+        if let Some(clock) = args.outside_execution_clock {
+            self.outside_execution.clock = clock;
+        }
+
+        if let Some(workers) = args.prover_workers {
+            self.prover.workers = Some(workers);
+        }
+        if let Some(queue_size) = args.prover_queue_size {
+            self.prover.queue_size = queue_size;
+        }
+
+        if args.tracer_otlp {
+            self.telemetry.otlp = true;
+        }
+        if let Some(endpoint) = &args.otlp_endpoint {
+            self.telemetry.otlp_endpoint = Some(endpoint.clone());
+        }
+
+        if self.outside_execution.expiry == 0 {
+            bail!("outside_execution.expiry must be greater than 0");
+        }
+        if self.prover.workers == Some(0) {
+            bail!("prover.workers must be greater than 0");
+        }
+        if self.server.worker_threads == Some(0) {
+            bail!("server.worker_threads must be greater than 0");
+        }
+        if self.readiness.timeout == 0 {
+            bail!("readiness.timeout must be greater than 0");
+        }
+        if self.tracking.poll_interval == 0 {
+            bail!("tracking.poll_interval must be greater than 0");
+        }
+        if self.tracking.max_bundles == 0 {
+            bail!("tracking.max_bundles must be greater than 0");
+        }
+
+        Ok(())
+    }
+}
+
+fn default_chains() -> BTreeMap<String, ChainConfig> {
+    BTreeMap::from([
+        (
+            "mainnet".to_string(),
+            ChainConfig::new(MAINNET)
+                .with_rpc_url(Url::parse("https://api.cartridge.gg/x/starknet/mainnet").unwrap()),
+        ),
+        (
+            "sepolia".to_string(),
+            ChainConfig::new(SEPOLIA)
+                .with_rpc_url(Url::parse("https://api.cartridge.gg/x/starknet/sepolia").unwrap()),
+        ),
+    ])
+}
+
+/// Parses a `--chain` value, `CHAIN_ID[=RPC_URL]`.
+fn parse_chain_arg(value: &str) -> anyhow::Result<(Felt, Option<Url>)> {
+    let (chain_id, rpc_url) = match value.split_once('=') {
+        Some((chain_id, rpc_url)) => (chain_id, Some(rpc_url)),
+        None => (value, None),
+    };
+
+    let chain_id = parse_chain_id(chain_id)
+        .map_err(|e| anyhow!("invalid chain id in --chain {value}: {e}"))?;
+    let rpc_url = rpc_url
+        .map(Url::parse)
+        .transpose()
+        .with_context(|| format!("invalid rpc url in --chain {value}"))?;
+
+    Ok((chain_id, rpc_url))
+}
+
+/// Parses a chain id given as a hex felt (`0x` prefixed) or a Cairo short string.
+pub fn parse_chain_id(value: &str) -> Result<Felt, String> {
+    if value.starts_with("0x") {
+        Felt::from_hex(value).map_err(|e| e.to_string())
+    } else {
+        cairo_short_string_to_felt(value).map_err(|e| e.to_string())
+    }
+}
+
+fn deserialize_chain_id<'de, D>(deserializer: D) -> Result<Felt, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    parse_chain_id(&value).map_err(serde::de::Error::custom)
+}
+
+fn default_relayer_max_fee() -> u128 {
+    DEFAULT_RELAYER_MAX_FEE
+}
+
+/// Amounts overflow TOML integers, they may also be given as decimal strings.
+fn deserialize_amount<'de, D>(deserializer: D) -> Result<u128, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Amount {
+        Integer(u64),
+        String(String),
+    }
+
+    match Amount::deserialize(deserializer)? {
+        Amount::Integer(value) => Ok(value.into()),
+        Amount::String(value) => value.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use starknet::macros::felt;
+    use std::path::PathBuf;
+
+    fn chain(config: &Config, chain_id: Felt) -> Option<&ChainConfig> {
+        config
+            .chains
+            .values()
+            .find(|chain| chain.chain_id == chain_id)
+    }
+
+    const CONFIG: &str = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 4000
+        runtime = "multi_thread"
+        worker_threads = 4
+
+        [vrf]
+        skip_public_key_check = true
+
+        [vrf.secret_key]
+        file = "/run/secrets/vrf.key"
+
+        [account]
+        address = "0x123"
+
+        [account.private_key]
+        keystore = "/run/secrets/account.json"
+        keystore_password_file = "/run/secrets/account.password"
+
+        [chains.katana]
+        chain_id = "WP_KATANA"
+        rpc_url = "http://localhost:5050"
+        vrf_account_address = "0x456"
+        allowed_rpc_urls = ["localhost:6060"]
+        allowed_executors = ["0x789"]
+        relayer_max_fee = "5000000000000000000"
+
+        [chains.katana.private_key]
+        value = "0x222"
+
+        [[chains.katana.relayers]]
+        address = "0xabc"
+        private_key = { value = "0x333" }
+
+        [outside_execution]
+        expiry = 300
+        client_rpc_url = "ignore"
+        version = "match_inner"
+        simulation = "estimate_fee"
+        signature_validation = "disabled"
+        clamp_to_inner = false
+        clock = "block"
+
+        [prover]
+        workers = 2
+        queue_size = 8
+
+        [telemetry]
+        otlp = true
+
+        [limits]
+        max_body_size = 1024
+        max_proof_batch_size = 10
+
+        [readiness]
+        timeout = 2
+        min_fee_token_balance = "100000000000000000000"
+
+        [tracking]
+        poll_interval = 2
+        retention = 3600
+        max_bundles = 500
+    "#;
+
+    #[test]
+    fn parses_config_file() {
+        let config = Config::from_toml(CONFIG).unwrap();
+
+        assert_eq!(config.server.host, "127.0.0.1");
+        assert_eq!(config.server.port, 4000);
+        assert_eq!(config.server.runtime, RuntimeFlavor::MultiThread);
+        assert_eq!(config.server.worker_threads, Some(4));
+        assert_eq!(config.prover.workers(), 2);
+        assert_eq!(config.prover.queue_size, 8);
+        assert_eq!(config.prover.retry_after, DEFAULT_PROVER_RETRY_AFTER);
+        assert_eq!(
+            config.vrf.secret_key.file,
+            Some(PathBuf::from("/run/secrets/vrf.key"))
+        );
+        assert!(config.vrf.skip_public_key_check);
+        assert_eq!(config.account.address, Some(felt!("0x123")));
+        assert_eq!(
+            config.account.private_key.keystore,
+            Some(PathBuf::from("/run/secrets/account.json"))
+        );
+        assert_eq!(config.outside_execution.expiry, 300);
+        assert_eq!(
+            config.outside_execution.client_rpc_url,
+            ClientRpcUrlPolicy::Ignore
+        );
+        assert_eq!(
+            config.outside_execution.version,
+            OutsideExecutionVersion::MatchInner
+        );
+        assert_eq!(
+            config.outside_execution.simulation,
+            SimulationMode::EstimateFee
+        );
+        assert_eq!(
+            config.outside_execution.signature_validation,
+            SignatureValidation::Disabled
+        );
+        assert!(!config.outside_execution.clamp_to_inner);
+        assert_eq!(config.outside_execution.clock, Clock::Block);
+        assert!(config.telemetry.otlp);
+        assert_eq!(config.limits.max_body_size, 1024);
+        assert_eq!(config.limits.max_proof_batch_size, 10);
+        assert_eq!(config.readiness.timeout, 2);
+        assert_eq!(config.readiness.fee_token, DEFAULT_FEE_TOKEN);
+        assert_eq!(
+            config.readiness.min_fee_token_balance,
+            100 * 10_u128.pow(18)
+        );
+        assert_eq!(config.tracking.poll_interval, 2);
+        assert_eq!(config.tracking.retention, 3600);
+        assert_eq!(config.tracking.max_bundles, 500);
+
+        let katana = chain(&config, cairo_short_string_to_felt("WP_KATANA").unwrap()).unwrap();
+        assert_eq!(katana.vrf_account_address, Some(felt!("0x456")));
+        assert_eq!(katana.allowed_executors, vec![felt!("0x789")]);
+        assert_eq!(katana.relayers.len(), 1);
+        assert_eq!(katana.relayers[0].address, felt!("0xabc"));
+        assert_eq!(
+            katana.relayers[0].private_key.value,
+            Some("0x333".to_string())
+        );
+        assert_eq!(katana.relayer_max_fee, 5 * 10_u128.pow(18));
+        assert_eq!(katana.private_key.value, Some("0x222".to_string()));
+        assert!(!katana.vrf_secret_key.is_set());
+        assert!(chain(&config, MAINNET).is_none());
+    }
+
+    #[test]
+    fn empty_config_uses_defaults() {
+        let config = Config::from_toml("").unwrap();
+
+        assert_eq!(config.server.host, DEFAULT_HOST);
+        assert_eq!(config.server.port, DEFAULT_PORT);
+        assert_eq!(config.server.runtime, RuntimeFlavor::CurrentThread);
+        assert_eq!(config.prover.queue_size, DEFAULT_PROVER_QUEUE_SIZE);
+        assert!(config.prover.workers() >= 1);
+        assert_eq!(config.readiness.timeout, DEFAULT_READINESS_TIMEOUT);
+        assert!(!config.vrf.skip_public_key_check);
+        assert_eq!(config.readiness.min_fee_token_balance, 0);
+        assert_eq!(
+            config.tracking.poll_interval,
+            DEFAULT_TRACKING_POLL_INTERVAL
+        );
+        assert_eq!(config.tracking.retention, DEFAULT_TRACKING_RETENTION);
+        assert_eq!(config.tracking.max_bundles, DEFAULT_TRACKING_MAX_BUNDLES);
+        assert_eq!(
+            config.outside_execution.expiry,
+            DEFAULT_OUTSIDE_EXECUTION_EXPIRY
+        );
+        assert_eq!(
+            config.outside_execution.signature_validation,
+            SignatureValidation::Rpc
+        );
+        assert!(config.outside_execution.clamp_to_inner);
+        assert_eq!(config.outside_execution.clock, Clock::System);
+        assert_eq!(
+            chain(&config, MAINNET).unwrap().relayer_max_fee,
+            DEFAULT_RELAYER_MAX_FEE
+        );
+        assert!(chain(&config, SEPOLIA).is_some());
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(Config::from_toml("[server]\nhots = \"0.0.0.0\"").is_err());
+    }
+
+    #[test]
+    fn args_override_config() {
+        let mut config = Config::from_toml(CONFIG).unwrap();
+        let args = Args::default()
+            .with_port(5000)
+            .with_account_address("0x789")
+            .with_account_private_key("0x111");
+
+        config.apply_args(&args).unwrap();
+
+        assert_eq!(config.server.host, "127.0.0.1");
+        assert_eq!(config.server.port, 5000);
+        assert_eq!(config.account.address, Some(felt!("0x789")));
+        assert_eq!(config.account.private_key.value, Some("0x111".to_string()));
+        assert_eq!(config.account.private_key.keystore, None);
+    }
+
+    #[test]
+    fn chain_args_add_or_update_chains() {
+        let mut config = Config::from_toml(CONFIG).unwrap();
+        let args = Args::default()
+            .with_chain("WP_KATANA=http://localhost:6060")
+            .with_chain("0x534e5f5345504f4c4941");
+
+        config.apply_args(&args).unwrap();
+
+        let katana = chain(&config, cairo_short_string_to_felt("WP_KATANA").unwrap()).unwrap();
+        assert_eq!(
+            katana.rpc_url,
+            Some(Url::parse("http://localhost:6060").unwrap())
+        );
+        assert_eq!(katana.vrf_account_address, Some(felt!("0x456")));
+
+        let sepolia = chain(&config, SEPOLIA).unwrap();
+        assert_eq!(sepolia.rpc_url, None);
+
+        assert!(config
+            .apply_args(&Args::default().with_chain("WP_KATANA=not a url"))
+            .is_err());
+    }
+}
@@ -1,86 +1,272 @@
+pub mod config;
 pub mod fmt;
+pub mod metrics;
 pub mod oracle;
+pub mod prover;
 pub mod routes;
 pub mod state;
 pub mod telemetry;
-pub mod utils;
 pub mod version;
+pub mod vrf;
 
 #[cfg(test)]
 pub mod tests {
     pub mod setup;
     pub mod test_info;
+    pub mod test_metrics;
+    pub mod test_nonces;
     pub mod test_outisde_execution;
+    pub mod test_proof;
+    pub mod test_public_key_check;
+    pub mod test_ready;
+    pub mod test_relay;
+    pub mod test_status;
 }
 
+use crate::config::{
+    ClientRpcUrlPolicy, Clock, Config, OutsideExecutionVersion, RuntimeFlavor, SignatureValidation,
+    SimulationMode,
+};
+use crate::metrics::{track_requests, vrf_metrics};
+use crate::routes::outside_execution::relay::vrf_outside_execution_relay;
 use crate::routes::outside_execution::vrf_outside_execution;
-use crate::routes::proof::vrf_proof;
+use crate::routes::proof::{vrf_proof, vrf_proof_batch};
+use crate::routes::ready::vrf_ready;
+use crate::routes::status::vrf_status;
+use crate::routes::verify::vrf_verify;
+use crate::state::secrets::{parse_vrf_secret_key, SecretOptions};
+use crate::state::vrf_account::check_vrf_public_keys;
 use crate::state::AppState;
 use crate::{routes::info::vrf_info, state::SharedState};
 use axum::{
+    extract::DefaultBodyLimit,
+    middleware,
     routing::{get, post},
     Router,
 };
 use clap::Parser;
+use starknet::core::types::Felt;
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use tokio::signal;
 use tower_http::trace::TraceLayer;
 use tracing::debug;
 
-use crate::telemetry::{OtelMakeSpan, OtlpConfig};
+use crate::telemetry::OtelMakeSpan;
 
 #[derive(Parser, Debug)]
 #[command(version = version::generate_short(), long_version = version::generate_long(), about, long_about = None)]
 pub struct Args {
-    /// http host
-    #[arg(long, default_value = "0.0.0.0")]
-    host: String,
+    /// Path to a TOML configuration file
+    #[arg(short, long, env = "VRF_CONFIG", value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// http host (defaults to `0.0.0.0`)
+    #[arg(long, env = "VRF_HOST")]
+    host: Option<String>,
+
+    /// http port (defaults to `3000`)
+    #[arg(short, long, env = "VRF_PORT")]
+    port: Option<u16>,
+
+    /// Tokio runtime flavor (defaults to `current-thread`).
+    #[arg(long, env = "VRF_RUNTIME", value_enum)]
+    runtime: Option<RuntimeFlavor>,
+
+    /// Worker threads of the `multi-thread` runtime (defaults to the number of cores).
+    #[arg(long, env = "VRF_WORKER_THREADS")]
+    worker_threads: Option<usize>,
 
-    /// http port
-    #[arg(short, long, default_value_t = 3000)]
-    port: u64,
+    /// Proofs computed concurrently (defaults to the number of cores).
+    #[arg(long = "prover.workers", env = "VRF_PROVER_WORKERS")]
+    prover_workers: Option<usize>,
+
+    /// Requests waiting for a prover worker before `503` is returned (defaults to `64`).
+    #[arg(long = "prover.queue-size", env = "VRF_PROVER_QUEUE_SIZE")]
+    prover_queue_size: Option<usize>,
+
+    /// VRF secret key, a non-zero Stark curve scalar (hex or decimal)
+    #[arg(
+        short,
+        long,
+        env = "VRF_SECRET_KEY",
+        hide_env_values = true,
+        value_parser = parse_vrf_secret_key
+    )]
+    secret_key: Option<Felt>,
 
-    /// Secret key
-    #[arg(short, long, required = true)]
-    secret_key: u64,
+    /// Path to a file containing the VRF secret key
+    #[arg(long, env = "VRF_SECRET_KEY_FILE", value_name = "PATH")]
+    secret_key_file: Option<PathBuf>,
+
+    /// Path to a Starknet JSON keystore holding the VRF secret key
+    #[arg(long, env = "VRF_SECRET_KEY_KEYSTORE", value_name = "PATH")]
+    secret_key_keystore: Option<PathBuf>,
+
+    /// Path to a file containing the VRF secret key keystore password (prompted if omitted)
+    #[arg(
+        long,
+        env = "VRF_SECRET_KEY_KEYSTORE_PASSWORD_FILE",
+        value_name = "PATH"
+    )]
+    secret_key_keystore_password_file: Option<PathBuf>,
+
+    /// Start even if a VRF account stores another public key than the VRF secret key's,
+    /// or its public key can't be read
+    #[arg(long, env = "VRF_SKIP_PUBLIC_KEY_CHECK")]
+    skip_public_key_check: bool,
 
     /// Account Address
-    #[arg(long, required = true)]
-    account_address: String,
+    #[arg(long, env = "VRF_ACCOUNT_ADDRESS")]
+    account_address: Option<String>,
 
     /// Account Private Key
-    #[arg(long, required = true)]
-    account_private_key: String,
+    #[arg(long, env = "VRF_ACCOUNT_PRIVATE_KEY", hide_env_values = true)]
+    account_private_key: Option<String>,
+
+    /// Path to a file containing the account private key
+    #[arg(long, env = "VRF_ACCOUNT_PRIVATE_KEY_FILE", value_name = "PATH")]
+    account_private_key_file: Option<PathBuf>,
+
+    /// Path to a Starknet JSON keystore holding the account private key
+    #[arg(long, env = "VRF_ACCOUNT_PRIVATE_KEY_KEYSTORE", value_name = "PATH")]
+    account_private_key_keystore: Option<PathBuf>,
+
+    /// Path to a file containing the account keystore password (prompted if omitted)
+    #[arg(
+        long,
+        env = "VRF_ACCOUNT_PRIVATE_KEY_KEYSTORE_PASSWORD_FILE",
+        value_name = "PATH"
+    )]
+    account_private_key_keystore_password_file: Option<PathBuf>,
+
+    /// Chain to serve with the default account, `CHAIN_ID[=RPC_URL]` (repeatable)
+    #[arg(
+        long = "chain",
+        env = "VRF_CHAINS",
+        value_delimiter = ',',
+        value_name = "CHAIN_ID[=RPC_URL]"
+    )]
+    chains: Vec<String>,
+
+    /// Validity window of the signed outside execution, in seconds (defaults to `600`).
+    #[arg(
+        long = "outside-execution.expiry",
+        env = "VRF_OUTSIDE_EXECUTION_EXPIRY",
+        value_name = "SECONDS"
+    )]
+    outside_execution_expiry: Option<u64>,
+
+    /// How the client supplied `rpc_url` is handled (defaults to `allowlist`).
+    #[arg(
+        long = "outside-execution.client-rpc-url",
+        env = "VRF_OUTSIDE_EXECUTION_CLIENT_RPC_URL",
+        value_enum
+    )]
+    client_rpc_url: Option<ClientRpcUrlPolicy>,
+
+    /// Version of the outside execution signed by the VRF account (defaults to `v2`).
+    #[arg(
+        long = "outside-execution.version",
+        env = "VRF_OUTSIDE_EXECUTION_VERSION",
+        value_enum
+    )]
+    outside_execution_version: Option<OutsideExecutionVersion>,
+
+    /// Pre-flight check of the final transaction (defaults to `disabled`).
+    #[arg(
+        long = "outside-execution.simulation",
+        env = "VRF_OUTSIDE_EXECUTION_SIMULATION",
+        value_enum
+    )]
+    outside_execution_simulation: Option<SimulationMode>,
+
+    /// How the user's signature is checked before co-signing (defaults to `rpc`).
+    #[arg(
+        long = "outside-execution.signature-validation",
+        env = "VRF_OUTSIDE_EXECUTION_SIGNATURE_VALIDATION",
+        value_enum
+    )]
+    outside_execution_signature_validation: Option<SignatureValidation>,
+
+    /// Restrict the outer window to the user's outside execution window (defaults to `true`).
+    #[arg(
+        long = "outside-execution.clamp-to-inner",
+        env = "VRF_OUTSIDE_EXECUTION_CLAMP_TO_INNER",
+        value_name = "BOOL"
+    )]
+    outside_execution_clamp_to_inner: Option<bool>,
+
+    /// Clock the outer window starts from (defaults to `system`).
+    #[arg(
+        long = "outside-execution.clock",
+        env = "VRF_OUTSIDE_EXECUTION_CLOCK",
+        value_enum
+    )]
+    outside_execution_clock: Option<Clock>,
 
     /// Enable the OpenTelemetry Protocol (OTLP) trace exporter.
-    #[arg(long = "tracer.otlp")]
+    #[arg(long = "tracer.otlp", env = "VRF_TRACER_OTLP")]
     tracer_otlp: bool,
 
     /// OTLP collector endpoint (defaults to `http://localhost:4317`).
     #[arg(
         long = "tracer.otlp-endpoint",
-        requires = "tracer_otlp",
+        env = "VRF_TRACER_OTLP_ENDPOINT",
         value_name = "URL"
     )]
     otlp_endpoint: Option<String>,
 }
 
 impl Args {
-    fn otlp_config(&self) -> Option<OtlpConfig> {
-        self.tracer_otlp.then(|| OtlpConfig {
-            endpoint: self.otlp_endpoint.clone(),
-        })
+    fn vrf_secret_key_options(&self) -> SecretOptions {
+        SecretOptions {
+            value: self.secret_key.map(|secret_key| secret_key.to_hex_string()),
+            file: self.secret_key_file.clone(),
+            keystore: self.secret_key_keystore.clone(),
+            keystore_password_file: self.secret_key_keystore_password_file.clone(),
+        }
+    }
+
+    fn account_private_key_options(&self) -> SecretOptions {
+        SecretOptions {
+            value: self.account_private_key.clone(),
+            file: self.account_private_key_file.clone(),
+            keystore: self.account_private_key_keystore.clone(),
+            keystore_password_file: self.account_private_key_keystore_password_file.clone(),
+        }
     }
 }
 
 impl Default for Args {
     fn default() -> Self {
         Args {
-            host: "0.0.0.0".into(),
-            port: 3000,
-            account_address: "0x123".into(),
-            account_private_key: "0x420".into(),
-            secret_key: 420,
+            config: None,
+            host: None,
+            port: None,
+            runtime: None,
+            worker_threads: None,
+            prover_workers: None,
+            prover_queue_size: None,
+            account_address: Some("0x123".into()),
+            account_private_key: Some("0x420".into()),
+            account_private_key_file: None,
+            account_private_key_keystore: None,
+            account_private_key_keystore_password_file: None,
+            secret_key: Some(Felt::from(420_u64)),
+            skip_public_key_check: false,
+            secret_key_file: None,
+            secret_key_keystore: None,
+            secret_key_keystore_password_file: None,
+            chains: vec![],
+            outside_execution_expiry: None,
+            client_rpc_url: None,
+            outside_execution_version: None,
+            outside_execution_simulation: None,
+            outside_execution_signature_validation: None,
+            outside_execution_clamp_to_inner: None,
+            outside_execution_clock: None,
             tracer_otlp: false,
             otlp_endpoint: None,
         }
@@ -89,49 +275,122 @@ impl Default for Args {
 
 #[allow(dead_code)]
 impl Args {
+    fn with_config(mut self, path: &str) -> Args {
+        self.config = Some(path.into());
+        self
+    }
     fn with_host(mut self, host: &str) -> Args {
-        self.host = host.into();
+        self.host = Some(host.into());
         self
     }
-    fn with_port(mut self, port: u64) -> Args {
-        self.port = port;
+    fn with_port(mut self, port: u16) -> Args {
+        self.port = Some(port);
         self
     }
     fn with_account_address(mut self, account_address: &str) -> Args {
-        self.account_address = account_address.into();
+        self.account_address = Some(account_address.into());
+        self
+    }
+    fn with_chain(mut self, chain: &str) -> Args {
+        self.chains.push(chain.into());
         self
     }
     fn with_account_private_key(mut self, account_private_key: &str) -> Args {
-        self.account_private_key = account_private_key.into();
+        self.account_private_key = Some(account_private_key.into());
+        self.account_private_key_file = None;
+        self.account_private_key_keystore = None;
         self
     }
-    fn with_secret_key(mut self, secret_key: u64) -> Args {
-        self.secret_key = secret_key;
+    fn with_account_private_key_file(mut self, path: &str) -> Args {
+        self.account_private_key = None;
+        self.account_private_key_file = Some(path.into());
+        self
+    }
+    fn with_secret_key(mut self, secret_key: Felt) -> Args {
+        self.secret_key = Some(secret_key);
+        self.secret_key_file = None;
+        self.secret_key_keystore = None;
+        self
+    }
+    fn with_secret_key_file(mut self, path: &str) -> Args {
+        self.secret_key = None;
+        self.secret_key_file = Some(path.into());
+        self
+    }
+    fn with_signature_validation(mut self, signature_validation: SignatureValidation) -> Args {
+        self.outside_execution_signature_validation = Some(signature_validation);
         self
     }
 }
 
 pub async fn create_app(app_state: AppState) -> Router {
+    metrics::install();
+
+    let max_body_size = app_state.config.limits.max_body_size;
     let shared_state = SharedState(Arc::new(RwLock::new(app_state)));
     Router::new()
         .route("/", get("OK"))
         .route("/info", get(vrf_info))
+        .route("/ready", get(vrf_ready))
         .route("/proof", post(vrf_proof))
+        .route("/proof/batch", post(vrf_proof_batch))
+        .route("/verify", post(vrf_verify))
         .route("/outside_execution", post(vrf_outside_execution))
+        .route(
+            "/outside_execution/relay",
+            post(vrf_outside_execution_relay),
+        )
+        .route("/status/{id}", get(vrf_status))
+        .route("/metrics", get(vrf_metrics))
+        .route_layer(middleware::from_fn(track_requests))
+        .layer(DefaultBodyLimit::max(max_body_size))
         .layer(TraceLayer::new_for_http().make_span_with(OtelMakeSpan))
         .with_state(shared_state)
 }
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() {
+fn main() -> anyhow::Result<()> {
     let args = Args::parse();
+    let config = Config::load(&args)?;
+
+    let mut runtime = match config.server.runtime {
+        RuntimeFlavor::CurrentThread => tokio::runtime::Builder::new_current_thread(),
+        RuntimeFlavor::MultiThread => {
+            let mut builder = tokio::runtime::Builder::new_multi_thread();
+            if let Some(worker_threads) = config.server.worker_threads {
+                builder.worker_threads(worker_threads);
+            }
+            builder
+        }
+    };
+
+    runtime.enable_all().build()?.block_on(serve(config))
+}
 
-    telemetry::init(args.otlp_config()).expect("failed to initialize telemetry");
+async fn serve(config: Config) -> anyhow::Result<()> {
+    telemetry::init(config.telemetry.otlp_config()).expect("failed to initialize telemetry");
 
-    let app_state = AppState::new().await;
+    let app_state = AppState::from_config(config).await?;
+
+    if !app_state.config.vrf.skip_public_key_check {
+        check_vrf_public_keys(
+            &app_state.chains,
+            Duration::from_secs(app_state.config.readiness.timeout),
+        )
+        .await?;
+    }
+    tokio::spawn(
+        app_state
+            .tracker
+            .clone()
+            .run(Duration::from_secs(app_state.config.tracking.poll_interval)),
+    );
+
+    let bind_addr = format!(
+        "{}:{}",
+        app_state.config.server.host, app_state.config.server.port
+    );
     let app = create_app(app_state).await;
 
-    let bind_addr = format!("{}:{}", args.host, args.port);
     let listener = tokio::net::TcpListener::bind(&bind_addr)
         .await
         .expect("Failed to bind to host/port, port already in use by another process. Change the host/port or terminate the other process.");
@@ -145,6 +404,8 @@ async fn main() {
 
     // Flush any buffered OTLP spans before exiting.
     telemetry::shutdown();
+
+    Ok(())
 }
 
 async fn shutdown_signal() {
@@ -0,0 +1,138 @@
+//! Prometheus metrics for vrf-server, served on `/metrics`.
+//!
+//! Metrics are recorded through the `metrics` facade into a process wide
+//! Prometheus recorder, installed once even when several apps are created
+//! (as in tests).
+
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use ::metrics::{counter, gauge, histogram};
+use axum::extract::{MatchedPath, Request};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use starknet::core::types::Felt;
+use starknet::core::utils::parse_cairo_short_string;
+use starknet::providers::ProviderError;
+
+pub const HTTP_REQUESTS_TOTAL: &str = "vrf_http_requests_total";
+pub const HTTP_REQUEST_DURATION_SECONDS: &str = "vrf_http_request_duration_seconds";
+pub const PROOF_DURATION_SECONDS: &str = "vrf_proof_duration_seconds";
+pub const RPC_DURATION_SECONDS: &str = "vrf_rpc_duration_seconds";
+pub const RPC_ERRORS_TOTAL: &str = "vrf_rpc_errors_total";
+pub const ERRORS_TOTAL: &str = "vrf_errors_total";
+pub const CHAIN_REQUESTS_TOTAL: &str = "vrf_chain_requests_total";
+pub const BUNDLES_PENDING: &str = "vrf_bundles_pending";
+pub const BUNDLES_TOTAL: &str = "vrf_bundles_total";
+pub const BUNDLE_SETTLE_DURATION_SECONDS: &str = "vrf_bundle_settle_duration_seconds";
+
+/// Histogram buckets in seconds, from a single proof to a slow RPC.
+const BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// Installs the Prometheus recorder on first call and returns its handle.
+pub fn install() -> PrometheusHandle {
+    HANDLE
+        .get_or_init(|| {
+            let recorder = PrometheusBuilder::new()
+                .set_buckets(BUCKETS)
+                .expect("buckets are not empty")
+                .build_recorder();
+            let handle = recorder.handle();
+
+            if ::metrics::set_global_recorder(recorder).is_err() {
+                tracing::warn!("a metrics recorder is already installed, /metrics will be empty");
+            }
+
+            handle
+        })
+        .clone()
+}
+
+// curl http://0.0.0.0:3000/metrics
+
+pub async fn vrf_metrics() -> impl IntoResponse {
+    install().render()
+}
+
+/// Counts requests and records their latency, labeled by method, matched route and status.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+
+    let response = next.run(request).await;
+
+    let labels = [
+        ("method", method),
+        ("route", route),
+        ("status", response.status().as_u16().to_string()),
+    ];
+    counter!(HTTP_REQUESTS_TOTAL, &labels).increment(1);
+    histogram!(HTTP_REQUEST_DURATION_SECONDS, &labels).record(start.elapsed());
+
+    response
+}
+
+pub fn record_proof(duration: Duration) {
+    histogram!(PROOF_DURATION_SECONDS).record(duration);
+}
+
+/// Records the latency of an RPC `method` made for `chain_id`, and counts it as an
+/// error when it failed.
+pub fn record_rpc(chain_id: Felt, method: &'static str, start: Instant, ok: bool) {
+    let chain = chain_label(chain_id);
+
+    histogram!(RPC_DURATION_SECONDS, "chain" => chain.clone(), "method" => method)
+        .record(start.elapsed());
+    if !ok {
+        counter!(RPC_ERRORS_TOTAL, "chain" => chain, "method" => method).increment(1);
+    }
+}
+
+/// Starknet errors are answers of the node about the request, anything else is an RPC failure.
+pub fn is_rpc_failure(error: &ProviderError) -> bool {
+    !matches!(error, ProviderError::StarknetError(_))
+}
+
+/// Counts an error returned to a client, labeled by its stable code.
+pub fn record_error(code: &'static str) {
+    counter!(ERRORS_TOTAL, "code" => code).increment(1);
+}
+
+pub fn record_chain_request(chain_id: Felt) {
+    counter!(CHAIN_REQUESTS_TOTAL, "chain" => chain_label(chain_id)).increment(1);
+}
+
+pub fn record_bundle_tracked(chain_id: Felt) {
+    gauge!(BUNDLES_PENDING, "chain" => chain_label(chain_id)).increment(1);
+}
+
+/// Counts a tracked bundle that landed, reverted or expired, labeled by that status,
+/// and records the time it stayed pending.
+pub fn record_bundle_settled(chain_id: Felt, status: &'static str, pending: Duration) {
+    let chain = chain_label(chain_id);
+
+    gauge!(BUNDLES_PENDING, "chain" => chain.clone()).decrement(1);
+    counter!(BUNDLES_TOTAL, "chain" => chain.clone(), "status" => status).increment(1);
+    histogram!(BUNDLE_SETTLE_DURATION_SECONDS, "chain" => chain, "status" => status)
+        .record(pending);
+}
+
+/// Stops counting a pending bundle dropped to make room for newer ones.
+pub fn record_bundle_evicted(chain_id: Felt) {
+    gauge!(BUNDLES_PENDING, "chain" => chain_label(chain_id)).decrement(1);
+}
+
+/// Chain ids are labeled by their short string, `SN_MAIN` rather than `0x534e5f4d41494e`.
+fn chain_label(chain_id: Felt) -> String {
+    parse_cairo_short_string(&chain_id).unwrap_or_else(|_| format!("{chain_id:#x}"))
+}
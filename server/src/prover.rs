@@ -0,0 +1,149 @@
+//! Bounded pool VRF proofs are computed on.
+//!
+//! Proving is CPU bound, jobs run on tokio's blocking threads so they never stall
+//! the async runtime. At most `workers` jobs run at once and at most `queue_size`
+//! requests wait for a worker, further requests are rejected with `503`.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::ProverConfig;
+use crate::metrics;
+use crate::routes::errors::Errors;
+
+#[derive(Clone)]
+pub struct ProverPool {
+    workers: Arc<Semaphore>,
+    slots: Arc<Semaphore>,
+    retry_after: u64,
+}
+
+impl ProverPool {
+    pub fn new(config: &ProverConfig) -> ProverPool {
+        let workers = config.workers();
+
+        ProverPool {
+            workers: Arc::new(Semaphore::new(workers)),
+            slots: Arc::new(Semaphore::new(workers + config.queue_size)),
+            retry_after: config.retry_after,
+        }
+    }
+
+    /// Runs `job` on a worker, fails with `Errors::Overloaded` when the queue is full.
+    pub async fn run<T, F>(&self, job: F) -> Result<T, Errors>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let mut results = self.run_all(vec![job]).await?;
+        Ok(results.pop().expect("one result per job"))
+    }
+
+    /// Runs `jobs` concurrently, up to the worker count, and returns their results in order.
+    ///
+    /// The whole batch takes a single queue slot.
+    pub async fn run_all<T, F>(&self, jobs: Vec<F>) -> Result<Vec<T>, Errors>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let slot = Arc::new(self.reserve_slot()?);
+
+        let mut handles = Vec::with_capacity(jobs.len());
+        for job in jobs {
+            let worker = self.acquire_worker().await?;
+            let slot = slot.clone();
+
+            // permits are held by the blocking task, they are released when the proof
+            // is done even if the request is dropped
+            handles.push(tokio::task::spawn_blocking(move || {
+                let _worker = worker;
+                let _slot = slot;
+                let start = Instant::now();
+                let result = job();
+                metrics::record_proof(start.elapsed());
+                result
+            }));
+        }
+
+        let mut results = Vec::with_capacity(handles.len());
+        for handle in handles {
+            results.push(
+                handle
+                    .await
+                    .map_err(|e| Errors::Internal(format!("prover task failed: {e}")))?,
+            );
+        }
+
+        Ok(results)
+    }
+
+    fn reserve_slot(&self) -> Result<OwnedSemaphorePermit, Errors> {
+        self.slots
+            .clone()
+            .try_acquire_owned()
+            .map_err(|_| Errors::Overloaded(self.retry_after))
+    }
+
+    async fn acquire_worker(&self) -> Result<OwnedSemaphorePermit, Errors> {
+        self.workers
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|_| Errors::Internal("prover pool is closed".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn pool(workers: usize, queue_size: usize) -> ProverPool {
+        ProverPool::new(&ProverConfig {
+            workers: Some(workers),
+            queue_size,
+            retry_after: 3,
+        })
+    }
+
+    #[tokio::test]
+    async fn runs_jobs_in_order() {
+        let pool = pool(2, 0);
+
+        let jobs: Vec<_> = (0..5).map(|i| move || i * 2).collect();
+        assert_eq!(pool.run_all(jobs).await.unwrap(), vec![0, 2, 4, 6, 8]);
+        assert_eq!(pool.run(|| "done").await.unwrap(), "done");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejects_jobs_when_queue_is_full() {
+        let pool = pool(1, 1);
+        let (release, blocked) = mpsc::channel::<()>();
+        let blocked = Arc::new(std::sync::Mutex::new(blocked));
+
+        // one running job and one waiting for the worker fill the pool
+        let running = {
+            let pool = pool.clone();
+            let blocked = blocked.clone();
+            tokio::spawn(async move { pool.run(move || blocked.lock().unwrap().recv()).await })
+        };
+        let waiting = {
+            let pool = pool.clone();
+            tokio::spawn(async move { pool.run(|| ()).await })
+        };
+
+        while pool.slots.available_permits() > 0 {
+            tokio::task::yield_now().await;
+        }
+
+        assert!(matches!(pool.run(|| ()).await, Err(Errors::Overloaded(3))));
+
+        release.send(()).unwrap();
+        assert!(running.await.unwrap().is_ok());
+        assert!(waiting.await.unwrap().is_ok());
+        assert!(pool.run(|| ()).await.is_ok());
+    }
+}
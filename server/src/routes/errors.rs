@@ -0,0 +1,297 @@
+use axum::extract::rejection::JsonRejection;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use starknet::core::utils::CairoShortStringToFeltError;
+use starknet::providers::ProviderError;
+
+use crate::metrics;
+
+/// Errors returned by every route.
+#[derive(Debug)]
+pub enum Errors {
+    InvalidJson(JsonRejection),
+    InvalidSeed(String),
+    BatchTooLarge(String),
+    InvalidProof(String),
+    MalformedProof(String),
+    NoRequestRandom,
+    NoCallAfterRequestRandom,
+    MultipleSeeds(String),
+    RequestRandomTargetMismatch(String),
+    RequestRandomCallerMismatch(String),
+    InvalidSignature(String),
+    ProviderError(String),
+    CairoSerdeError(String),
+    RequestContextError(String),
+    UnknownChain(String),
+    RpcUrlRejected(String),
+    ExecutorRejected(String),
+    SimulationReverted(String),
+    OutsideExecutionExpired(String),
+    RelayerUnavailable(String),
+    RelayFeeTooHigh(String),
+    RelayFailed(String),
+    InvalidBundleId(String),
+    BundleNotFound(String),
+    CairoShortStringToFeltError(String),
+    UrlParserError(String),
+    /// The prover queue is full, retry after the given number of seconds.
+    Overloaded(u64),
+    Internal(String),
+}
+
+/// JSON body of an error response, `code` is stable and meant to be matched by clients.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+impl Errors {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Errors::InvalidJson(rejection) => rejection.status(),
+            Errors::InvalidSeed(_)
+            | Errors::BatchTooLarge(_)
+            | Errors::MalformedProof(_)
+            | Errors::InvalidBundleId(_)
+            | Errors::CairoSerdeError(_)
+            | Errors::RequestContextError(_)
+            | Errors::UnknownChain(_)
+            | Errors::CairoShortStringToFeltError(_)
+            | Errors::UrlParserError(_) => StatusCode::BAD_REQUEST,
+            Errors::RpcUrlRejected(_) | Errors::ExecutorRejected(_) => StatusCode::FORBIDDEN,
+            Errors::BundleNotFound(_) => StatusCode::NOT_FOUND,
+            Errors::NoRequestRandom
+            | Errors::NoCallAfterRequestRandom
+            | Errors::MultipleSeeds(_)
+            | Errors::RequestRandomTargetMismatch(_)
+            | Errors::RequestRandomCallerMismatch(_)
+            | Errors::InvalidSignature(_)
+            | Errors::InvalidProof(_)
+            | Errors::SimulationReverted(_)
+            | Errors::OutsideExecutionExpired(_)
+            | Errors::RelayFeeTooHigh(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Errors::ProviderError(_) | Errors::RelayFailed(_) => StatusCode::BAD_GATEWAY,
+            Errors::RelayerUnavailable(_) | Errors::Overloaded(_) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            Errors::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Errors::InvalidJson(_) => "invalid_json",
+            Errors::InvalidSeed(_) => "invalid_seed",
+            Errors::BatchTooLarge(_) => "batch_too_large",
+            Errors::InvalidProof(_) => "invalid_proof",
+            Errors::MalformedProof(_) => "malformed_proof",
+            Errors::NoRequestRandom => "no_request_random",
+            Errors::NoCallAfterRequestRandom => "no_call_after_request_random",
+            Errors::MultipleSeeds(_) => "multiple_seeds",
+            Errors::RequestRandomTargetMismatch(_) => "request_random_target_mismatch",
+            Errors::RequestRandomCallerMismatch(_) => "request_random_caller_mismatch",
+            Errors::InvalidSignature(_) => "invalid_signature",
+            Errors::ProviderError(_) => "provider_error",
+            Errors::CairoSerdeError(_) => "cairo_serde_error",
+            Errors::RequestContextError(_) => "invalid_request_context",
+            Errors::UnknownChain(_) => "unknown_chain",
+            Errors::RpcUrlRejected(_) => "rpc_url_rejected",
+            Errors::ExecutorRejected(_) => "executor_rejected",
+            Errors::SimulationReverted(_) => "simulation_reverted",
+            Errors::OutsideExecutionExpired(_) => "outside_execution_expired",
+            Errors::RelayerUnavailable(_) => "relayer_unavailable",
+            Errors::RelayFeeTooHigh(_) => "relay_fee_too_high",
+            Errors::RelayFailed(_) => "relay_failed",
+            Errors::InvalidBundleId(_) => "invalid_bundle_id",
+            Errors::BundleNotFound(_) => "bundle_not_found",
+            Errors::CairoShortStringToFeltError(_) => "invalid_short_string",
+            Errors::UrlParserError(_) => "invalid_url",
+            Errors::Overloaded(_) => "overloaded",
+            Errors::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        let (message, details) = match self {
+            Errors::InvalidJson(rejection) => {
+                (format!("Invalid JSON: {}", rejection.body_text()), None)
+            }
+            Errors::InvalidSeed(msg) => (format!("Invalid seed: {msg}"), None),
+            Errors::BatchTooLarge(msg) => (format!("Batch too large: {msg}"), None),
+            Errors::InvalidProof(msg) => (format!("Invalid proof: {msg}"), None),
+            Errors::MalformedProof(msg) => (format!("Malformed proof: {msg}"), None),
+            Errors::NoRequestRandom => ("No request_random call".to_string(), None),
+            Errors::NoCallAfterRequestRandom => ("No call after request_random".to_string(), None),
+            Errors::MultipleSeeds(msg) => (format!("Multiple seeds: {msg}"), None),
+            Errors::RequestRandomTargetMismatch(msg) => {
+                (format!("Invalid request_random target: {msg}"), None)
+            }
+            Errors::RequestRandomCallerMismatch(msg) => {
+                (format!("Invalid request_random caller: {msg}"), None)
+            }
+            Errors::InvalidSignature(msg) => (format!("Invalid signature: {msg}"), None),
+            Errors::ProviderError(msg) => (format!("Provider error: {msg}"), None),
+            Errors::CairoSerdeError(msg) => (format!("Cairo serde error: {msg}"), None),
+            Errors::RequestContextError(msg) => (format!("Request context error: {msg}"), None),
+            Errors::UnknownChain(chain_id) => (
+                format!("Unknown chain: {chain_id}"),
+                Some(json!({ "chain_id": chain_id })),
+            ),
+            Errors::RpcUrlRejected(msg) => (format!("Rpc url rejected: {msg}"), None),
+            Errors::ExecutorRejected(msg) => (format!("Executor rejected: {msg}"), None),
+            Errors::SimulationReverted(revert_reason) => (
+                "Simulation reverted".to_string(),
+                Some(json!({ "revert_reason": revert_reason })),
+            ),
+            Errors::OutsideExecutionExpired(msg) => {
+                (format!("Outside execution expired: {msg}"), None)
+            }
+            Errors::RelayerUnavailable(msg) => (format!("Relayer unavailable: {msg}"), None),
+            Errors::RelayFeeTooHigh(msg) => (format!("Relay fee too high: {msg}"), None),
+            Errors::RelayFailed(msg) => (format!("Relay failed: {msg}"), None),
+            Errors::InvalidBundleId(id) => (format!("Invalid bundle id: {id}"), None),
+            Errors::BundleNotFound(id) => (format!("Bundle not found: {id}"), None),
+            Errors::CairoShortStringToFeltError(msg) => (format!("Shortstring error: {msg}"), None),
+            Errors::UrlParserError(msg) => (format!("Url parser error: {msg}"), None),
+            Errors::Overloaded(retry_after) => (
+                "Too many pending proofs".to_string(),
+                Some(json!({ "retry_after": retry_after })),
+            ),
+            // internal details are logged, not returned
+            Errors::Internal(_) => ("Internal error".to_string(), None),
+        };
+
+        ErrorBody {
+            code: self.code().to_string(),
+            message,
+            details,
+        }
+    }
+}
+
+impl IntoResponse for Errors {
+    fn into_response(self) -> axum::response::Response {
+        if let Errors::Internal(msg) = &self {
+            tracing::error!("internal error: {msg}");
+        }
+        metrics::record_error(self.code());
+
+        let mut response = (self.status(), Json(self.body())).into_response();
+
+        if let Errors::Overloaded(retry_after) = self {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+
+        response
+    }
+}
+
+impl From<JsonRejection> for Errors {
+    fn from(value: JsonRejection) -> Self {
+        Errors::InvalidJson(value)
+    }
+}
+
+impl From<ProviderError> for Errors {
+    fn from(value: ProviderError) -> Self {
+        Errors::ProviderError(value.to_string())
+    }
+}
+
+impl From<cainome_cairo_serde::Error> for Errors {
+    fn from(value: cainome_cairo_serde::Error) -> Self {
+        Errors::CairoSerdeError(value.to_string())
+    }
+}
+
+impl From<CairoShortStringToFeltError> for Errors {
+    fn from(value: CairoShortStringToFeltError) -> Self {
+        Errors::CairoShortStringToFeltError(value.to_string())
+    }
+}
+
+impl From<url::ParseError> for Errors {
+    fn from(value: url::ParseError) -> Self {
+        Errors::UrlParserError(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_body_has_code_message_and_details() {
+        let body = serde_json::to_value(Errors::UnknownChain("WP_UNKNOWN".into()).body()).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "code": "unknown_chain",
+                "message": "Unknown chain: WP_UNKNOWN",
+                "details": { "chain_id": "WP_UNKNOWN" },
+            })
+        );
+
+        let body = serde_json::to_value(Errors::NoRequestRandom.body()).unwrap();
+        assert_eq!(
+            body,
+            json!({ "code": "no_request_random", "message": "No request_random call" })
+        );
+    }
+
+    #[test]
+    fn error_status_codes() {
+        assert_eq!(
+            Errors::InvalidSeed("0xzz".into()).status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            Errors::MalformedProof("c".into()).status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            Errors::InvalidProof("verification failed".into()).status(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            Errors::NoRequestRandom.status(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            Errors::ProviderError("timeout".into()).status(),
+            StatusCode::BAD_GATEWAY
+        );
+        assert_eq!(
+            Errors::RelayerUnavailable("WP_KATANA".into()).status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            Errors::Internal("prove".into()).status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn overloaded_sets_retry_after() {
+        let response = Errors::Overloaded(2).into_response();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+    }
+
+    #[test]
+    fn internal_errors_hide_details() {
+        let body = Errors::Internal("secret key parse failed".into()).body();
+        assert_eq!(body.code, "internal_error");
+        assert_eq!(body.message, "Internal error");
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{state::SharedState, utils::format};
+use crate::{state::SharedState, vrf::base_field_to_felt};
 use ark_ec::short_weierstrass::Affine;
 use axum::{extract::State, Json};
 use serde::{Deserialize, Serialize};
@@ -13,16 +13,17 @@ pub struct InfoResult {
 impl InfoResult {
     pub fn from_public_key(public_key: Affine<StarkCurve>) -> InfoResult {
         InfoResult {
-            public_key_x: format(public_key.x),
-            public_key_y: format(public_key.y),
+            public_key_x: base_field_to_felt(public_key.x).to_hex_string(),
+            public_key_y: base_field_to_felt(public_key.y).to_hex_string(),
         }
     }
 }
 
 // curl http://0.0.0.0:3000/info
 
+/// Public key of the default VRF key, chains with their own `vrf_secret_key` are not covered.
 pub async fn vrf_info(State(state): State<SharedState>) -> Json<InfoResult> {
-    let public_key = state.read().unwrap().public_key;
+    let public_key = state.read().unwrap().vrf_key.public_key;
 
     Json(InfoResult::from_public_key(public_key))
 }
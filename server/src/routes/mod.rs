@@ -1,3 +1,7 @@
+pub mod errors;
 pub mod info;
 pub mod outside_execution;
 pub mod proof;
+pub mod ready;
+pub mod status;
+pub mod verify;
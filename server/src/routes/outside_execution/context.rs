@@ -1,30 +1,48 @@
-use ark_ec::short_weierstrass::Affine;
 use cainome_cairo_serde::ContractAddress;
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use stark_vrf::StarkCurve;
 use starknet::{
-    providers::{jsonrpc::HttpTransport, JsonRpcClient, Url},
+    core::{
+        types::{BlockId, BlockTag, MaybePreConfirmedBlockWithTxHashes},
+        utils::parse_cairo_short_string,
+    },
+    providers::{jsonrpc::HttpTransport, JsonRpcClient, Provider, Url},
     signers::LocalWallet,
 };
 use starknet_crypto::Felt;
+use std::time::Instant;
 
-use crate::{routes::outside_execution::Errors, state::AppState};
+use crate::{
+    config::Clock,
+    metrics,
+    routes::{
+        errors::Errors,
+        outside_execution::{rpc_policy::resolve_rpc_url, ANY_CALLER},
+    },
+    state::{chains::ChainEntry, AppState},
+    vrf::VrfKey,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RequestContext {
     pub chain_id: Felt,
     pub rpc_url: Option<Url>,
+    /// Only account allowed to submit the VRF bundle, must be allowed for the chain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub executor: Option<Felt>,
 }
 
 #[derive(Debug)]
 pub struct VrfContext {
     pub chain_id: Felt,
     pub provider: JsonRpcClient<HttpTransport>,
+    pub client_rpc_url: bool,
     //
-    pub secret_key: String,
-    pub public_key: Affine<StarkCurve>,
+    pub vrf_key: VrfKey,
     pub vrf_account_address: ContractAddress,
     pub vrf_signer: LocalWallet,
+    /// Caller of the VRF account outside execution, `ANY_CALLER` unless an executor was requested.
+    pub caller: ContractAddress,
 }
 
 impl VrfContext {
@@ -32,37 +50,134 @@ impl VrfContext {
         request_context: RequestContext,
         app_state: &AppState,
     ) -> Result<Self, Errors> {
-        use starknet::core::chain_id::{MAINNET, SEPOLIA};
-
         let chain_id = request_context.chain_id;
+        let chain = app_state.chains.get(chain_id).ok_or_else(|| {
+            Errors::UnknownChain(
+                parse_cairo_short_string(&chain_id).unwrap_or_else(|_| format!("{chain_id:#x}")),
+            )
+        })?;
+        metrics::record_chain_request(chain_id);
 
-        let rpc_url = match request_context.rpc_url {
-            Some(rpc_url) => rpc_url,
-
-            None if request_context.chain_id == MAINNET => {
-                Url::parse("https://api.cartridge.gg/x/starknet/mainnet").unwrap()
-            }
-
-            None if request_context.chain_id == SEPOLIA => {
-                Url::parse("https://api.cartridge.gg/x/starknet/sepolia").unwrap()
-            }
+        let rpc_url = resolve_rpc_url(
+            app_state.config.outside_execution.client_rpc_url,
+            chain,
+            request_context.rpc_url,
+        )?;
 
-            None => {
-                return Err(Errors::RequestContextError(
-                    "no rpc_url provided".to_owned(),
-                ))
-            }
-        };
+        let caller = resolve_caller(chain, request_context.executor)?;
 
-        let provider = JsonRpcClient::new(HttpTransport::new(rpc_url));
+        let provider = JsonRpcClient::new(HttpTransport::new(rpc_url.url));
 
         Ok(VrfContext {
             chain_id,
             provider,
-            secret_key: app_state.secret_key.clone(),
-            public_key: app_state.public_key,
-            vrf_account_address: app_state.vrf_account_address,
-            vrf_signer: app_state.vrf_signer.clone(),
+            client_rpc_url: rpc_url.from_client,
+            vrf_key: chain.vrf_key.clone(),
+            vrf_account_address: chain.vrf_account_address,
+            vrf_signer: chain.vrf_signer.clone(),
+            caller,
         })
     }
 }
+
+/// Restricts the outside execution to `executor` when it is allowed for `chain`.
+fn resolve_caller(chain: &ChainEntry, executor: Option<Felt>) -> Result<ContractAddress, Errors> {
+    match executor {
+        None => Ok(ANY_CALLER),
+        Some(executor) if chain.allowed_executors.contains(&executor) => {
+            Ok(ContractAddress(executor))
+        }
+        Some(executor) => Err(Errors::ExecutorRejected(format!(
+            "{executor:#x} is not allowed for chain {}",
+            chain.name
+        ))),
+    }
+}
+
+impl VrfContext {
+    /// Ensures a client supplied RPC serves the requested chain before it is
+    /// trusted for any storage read.
+    pub async fn verify_rpc_chain_id(&self) -> Result<(), Errors> {
+        if !self.client_rpc_url {
+            return Ok(());
+        }
+
+        let start = Instant::now();
+        let rpc_chain_id = self.provider.chain_id().await;
+        metrics::record_rpc(self.chain_id, "chain_id", start, rpc_chain_id.is_ok());
+        let rpc_chain_id = rpc_chain_id?;
+
+        if rpc_chain_id != self.chain_id {
+            return Err(Errors::RpcUrlRejected(format!(
+                "rpc chain id {rpc_chain_id:#x} does not match requested chain id {:#x}",
+                self.chain_id
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Current time according to `clock`, in seconds since the epoch.
+    pub async fn now(&self, clock: Clock) -> Result<u64, Errors> {
+        match clock {
+            Clock::System => Ok(Utc::now().timestamp() as u64),
+            Clock::Block => {
+                let start = Instant::now();
+                let block = self
+                    .provider
+                    .get_block_with_tx_hashes(BlockId::Tag(BlockTag::Latest))
+                    .await;
+                metrics::record_rpc(
+                    self.chain_id,
+                    "get_block_with_tx_hashes",
+                    start,
+                    block.is_ok(),
+                );
+
+                Ok(match block? {
+                    MaybePreConfirmedBlockWithTxHashes::Block(block) => block.timestamp,
+                    MaybePreConfirmedBlockWithTxHashes::PreConfirmedBlock(block) => block.timestamp,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::relayers::RelayerPool;
+    use starknet::macros::felt;
+    use starknet::signers::SigningKey;
+
+    fn chain() -> ChainEntry {
+        ChainEntry {
+            name: "katana".into(),
+            chain_id: felt!("0x57505f4b4154414e41"),
+            rpc_url: None,
+            allowed_rpc_urls: vec![],
+            allowed_executors: vec![felt!("0x789")],
+            relayers: RelayerPool::default(),
+            vrf_account_address: ContractAddress(felt!("0x123")),
+            vrf_signer: LocalWallet::from(SigningKey::from_secret_scalar(felt!("0x111"))),
+            vrf_key: VrfKey::from_secret_key(felt!("0x1a4")),
+        }
+    }
+
+    #[test]
+    fn caller_defaults_to_any_caller() {
+        assert_eq!(resolve_caller(&chain(), None).unwrap(), ANY_CALLER);
+    }
+
+    #[test]
+    fn caller_is_restricted_to_allowed_executors() {
+        assert_eq!(
+            resolve_caller(&chain(), Some(felt!("0x789"))).unwrap(),
+            ContractAddress(felt!("0x789"))
+        );
+        assert!(matches!(
+            resolve_caller(&chain(), Some(felt!("0x999"))),
+            Err(Errors::ExecutorRejected(_))
+        ));
+    }
+}
@@ -1,26 +1,35 @@
 pub mod context;
+pub mod relay;
+pub mod rpc_policy;
 pub mod signature;
+pub mod simulation;
 pub mod types;
 pub mod vrf_types;
+use crate::config::{OutsideExecutionVersion, SignatureValidation, SimulationMode};
+use crate::routes::errors::Errors;
 use crate::routes::outside_execution::context::{RequestContext, VrfContext};
-use crate::routes::outside_execution::signature::sign_outside_execution;
+use crate::routes::outside_execution::signature::{
+    sign_outside_execution, verify_outside_execution_signature,
+};
+use crate::routes::outside_execution::simulation::simulate_call;
 use crate::routes::outside_execution::types::{
-    get_calls, Call, OutsideExecution, OutsideExecutionV2, SignedOutsideExecution,
+    get_calls, get_window, Call, ExecutionWindow, OutsideExecution, OutsideExecutionV2,
+    OutsideExecutionV3, SignedOutsideExecution,
 };
 use crate::routes::outside_execution::vrf_types::{build_submit_random_call, RequestRandom};
-use crate::state::SharedState;
+use crate::state::nonces::NonceReservation;
+use crate::state::tracker::{BundleStatus, BundleVersion, TrackedBundle};
+use crate::state::{AppState, SharedState};
+use account_sdk::hash::MessageHashRev1;
+use axum::extract::rejection::JsonRejection;
 use axum::extract::State;
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
 use axum::Json;
 use cainome_cairo_serde::{CairoSerde, ContractAddress};
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use starknet::core::types::Felt;
-use starknet::core::utils::CairoShortStringToFeltError;
+use starknet::core::types::{FeeEstimate, Felt};
 use starknet::macros::felt;
-use starknet::providers::ProviderError;
-use starknet::signers::{LocalWallet, SigningKey};
+use starknet_crypto::poseidon_hash_many;
 use tracing::debug;
 
 pub const ANY_CALLER: ContractAddress = ContractAddress(felt!("0x414e595f43414c4c4552")); // ANY_CALLER
@@ -31,9 +40,12 @@ pub struct OutsideExecutionRequest {
     pub context: RequestContext,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OutsideExecutionResult {
     pub result: SignedOutsideExecution,
+    /// Fee estimate of the `execute_from_outside` call, set when simulation is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_estimate: Option<FeeEstimate>,
 }
 
 // receive an OutsideExecution
@@ -43,155 +55,254 @@ pub struct OutsideExecutionResult {
 
 pub async fn vrf_outside_execution(
     State(state): State<SharedState>,
-    Json(payload): Json<OutsideExecutionRequest>,
+    payload: Result<Json<OutsideExecutionRequest>, JsonRejection>,
 ) -> Result<Json<OutsideExecutionResult>, Errors> {
+    let Json(payload) = payload?;
     debug!("received payload {payload:?}");
 
     let app_state = state.get().await;
 
-    let signed_outside_execution = payload.request.clone();
-    let outside_execution = signed_outside_execution.outside_execution.clone();
+    let request = payload.request;
     let vrf_context = VrfContext::build_from(payload.context, &app_state)?;
+    let nonce = derive_outer_nonce(vrf_context.chain_id, &request);
 
-    let (maybe_request_random_call, position) =
-        RequestRandom::get_request_random_call(&outside_execution);
-
-    if maybe_request_random_call.is_none() {
-        return Err(Errors::NoRequestRandom);
-    }
-    if position == get_calls(&outside_execution).len() {
-        return Err(Errors::NoCallAfterRequestRandom);
-    }
+    // retries get the bundle built for the first request while it is valid, whatever
+    // their `Idempotency-Key`
+    let (app_state, vrf_context) = (&app_state, &vrf_context);
+    let result = app_state
+        .bundles
+        .get_or_try_insert(
+            (nonce, vrf_context.caller.0),
+            Utc::now().timestamp() as u64,
+            move || async move {
+                let bundle = build_bundle(app_state, vrf_context, request, nonce).await?;
+                bundle.track(app_state, vrf_context, nonce, None);
+                Ok::<_, Errors>(bundle.keep())
+            },
+        )
+        .await?;
 
-    let request_random_call = maybe_request_random_call.unwrap();
-    let request_random = RequestRandom::cairo_deserialize(&request_random_call.calldata, 0)?;
+    Ok(Json(result))
+}
 
-    let seed = request_random.compute_seed(&vrf_context).await?;
+/// Nonce of the VRF account outside execution wrapping `inner`, derived from the chain
+/// id and the SNIP-12 hash of `inner`. Bundles built for the same inner execution share
+/// it and its `Source::Nonce` reservation, so they get the same seeds and at most one
+/// of them can execute.
+pub fn derive_outer_nonce(chain_id: Felt, inner: &SignedOutsideExecution) -> Felt {
+    let inner_hash = inner
+        .outside_execution
+        .get_message_hash_rev_1(chain_id, inner.address);
 
-    let sumbit_random_call = build_submit_random_call(&vrf_context, seed);
-    let execute_from_outside_call = signed_outside_execution.build_execute_from_outside_call();
+    poseidon_hash_many(&[chain_id, inner_hash])
+}
 
-    debug!("request_random: {:?}", request_random);
-    debug!("seed: {:?}", seed);
+/// Signed bundle of a request, its `Source::Nonce` nonce stays reserved once kept.
+pub struct Bundle {
+    pub result: OutsideExecutionResult,
+    pub expires_at: u64,
+    nonce_reservation: Option<NonceReservation>,
+}
 
-    let calls = vec![sumbit_random_call, execute_from_outside_call];
+impl Bundle {
+    /// Keeps the nonce reservation once the bundle is handed out, and returns it with
+    /// the timestamp it expires at.
+    pub fn keep(self) -> (OutsideExecutionResult, u64) {
+        if let Some(nonce_reservation) = self.nonce_reservation {
+            nonce_reservation.keep();
+        }
 
-    let signed_outside_execution = build_signed_outside_execution_v2(
-        vrf_context.vrf_account_address.0,
-        vrf_context.vrf_signer,
-        vrf_context.chain_id,
-        calls,
-    )
-    .await;
+        (self.result, self.expires_at)
+    }
 
-    Ok(Json(OutsideExecutionResult {
-        result: signed_outside_execution,
-    }))
+    /// Tracks the bundle once handed out, see [`crate::state::tracker`].
+    pub fn track(
+        &self,
+        app_state: &AppState,
+        vrf_context: &VrfContext,
+        nonce: Felt,
+        transaction_hash: Option<Felt>,
+    ) {
+        app_state.tracker.track(
+            TrackedBundle {
+                nonce,
+                chain_id: vrf_context.chain_id,
+                vrf_account_address: vrf_context.vrf_account_address.0,
+                version: match self.result.result.outside_execution {
+                    OutsideExecution::V2(_) => BundleVersion::V2,
+                    OutsideExecution::V3(_) => BundleVersion::V3,
+                },
+                transaction_hash,
+                expires_at: self.expires_at,
+                status: BundleStatus::Pending,
+            },
+            vrf_context.provider.clone(),
+            self.nonce_reservation
+                .as_ref()
+                .map(|reservation| (reservation.key(), reservation.nonce())),
+        );
+    }
 }
 
-pub async fn build_signed_outside_execution_v2(
-    account_address: Felt,
-    signer: LocalWallet,
-    chain_id: Felt,
-    calls: Vec<Call>,
-) -> SignedOutsideExecution {
-    let outside_execution = build_outside_execution_v2(calls);
+/// Builds the signed bundle of `signed_outside_execution`.
+pub async fn build_bundle(
+    app_state: &AppState,
+    vrf_context: &VrfContext,
+    signed_outside_execution: SignedOutsideExecution,
+    nonce: Felt,
+) -> Result<Bundle, Errors> {
+    let outside_execution = signed_outside_execution.outside_execution.clone();
 
-    let signature =
-        sign_outside_execution(&outside_execution, chain_id, account_address, signer).await;
+    let user_calls = get_calls(&outside_execution);
+    let request_random_calls = RequestRandom::get_request_random_calls(&outside_execution);
 
-    SignedOutsideExecution {
-        address: account_address,
-        outside_execution,
-        signature,
+    if request_random_calls.is_empty() {
+        return Err(Errors::NoRequestRandom);
     }
-}
-pub fn build_outside_execution_v2(calls: Vec<Call>) -> OutsideExecution {
-    let now = Utc::now().timestamp() as u64;
-    OutsideExecution::V2(OutsideExecutionV2 {
-        caller: ANY_CALLER,
-        execute_after: 0,
-        execute_before: now + 600,
-        calls,
-        nonce: SigningKey::from_random().secret_scalar(),
-    })
-}
 
-#[derive(Debug)]
-pub enum Errors {
-    NoRequestRandom,
-    NoCallAfterRequestRandom,
-    ProviderError(String),
-    CairoSerdeError(String),
-    RequestContextError(String),
-    CairoShortStringToFeltError(String),
-    UrlParserError(String),
-}
+    let mut requests = vec![];
+    for (position, request_random_call) in request_random_calls {
+        if position + 1 == user_calls.len() {
+            return Err(Errors::NoCallAfterRequestRandom);
+        }
 
-impl IntoResponse for Errors {
-    fn into_response(self) -> axum::response::Response {
-        match self {
-            Errors::NoRequestRandom => (
-                StatusCode::NOT_FOUND,
-                Json("No request_random call".to_string()),
-            )
-                .into_response(),
-            Errors::NoCallAfterRequestRandom => (
-                StatusCode::NOT_FOUND,
-                Json("No call after request_random".to_string()),
-            )
-                .into_response(),
-            Errors::ProviderError(msg) => (
-                StatusCode::NOT_FOUND,
-                Json(format!("Provider error: {msg}").to_string()),
-            )
-                .into_response(),
-            Errors::CairoSerdeError(msg) => (
-                StatusCode::NOT_FOUND,
-                Json(format!("Cairo serde error: {msg}").to_string()),
-            )
-                .into_response(),
-            Errors::RequestContextError(msg) => (
-                StatusCode::NOT_FOUND,
-                Json(format!("Request context error: {msg}").to_string()),
-            )
-                .into_response(),
-            Errors::CairoShortStringToFeltError(msg) => (
-                StatusCode::NOT_FOUND,
-                Json(format!("Shortstring error: {msg}").to_string()),
-            )
-                .into_response(),
-            Errors::UrlParserError(msg) => (
-                StatusCode::NOT_FOUND,
-                Json(format!("Url parser error: {msg}").to_string()),
-            )
-                .into_response(),
+        if request_random_call.to != vrf_context.vrf_account_address {
+            return Err(Errors::RequestRandomTargetMismatch(format!(
+                "request_random targets {:#x} instead of VRF account {:#x}",
+                request_random_call.to.0, vrf_context.vrf_account_address.0
+            )));
         }
+
+        let request_random = RequestRandom::cairo_deserialize(&request_random_call.calldata, 0)?;
+        request_random.ensure_caller_called_after(&outside_execution, position)?;
+
+        requests.push(request_random);
     }
-}
 
-impl From<ProviderError> for Errors {
-    fn from(value: ProviderError) -> Self {
-        Errors::ProviderError(value.to_string())
+    vrf_context.verify_rpc_chain_id().await?;
+
+    if app_state.config.outside_execution.signature_validation == SignatureValidation::Rpc {
+        verify_outside_execution_signature(&signed_outside_execution, vrf_context).await?;
     }
-}
 
-impl From<cainome_cairo_serde::Error> for Errors {
-    fn from(value: cainome_cairo_serde::Error) -> Self {
-        Errors::CairoSerdeError(value.to_string())
+    let config = &app_state.config.outside_execution;
+    let now = vrf_context.now(config.clock).await?;
+    let mut window = ExecutionWindow::from_now(now, config.expiry);
+    if config.clamp_to_inner {
+        // the outer outside execution must not outlive the user's one
+        window = window.clamp_to(get_window(&outside_execution));
+    }
+    if window.is_expired_at(now) {
+        return Err(Errors::OutsideExecutionExpired(format!(
+            "window ({}, {}) is closed at {now}",
+            window.execute_after, window.execute_before
+        )));
     }
+
+    let stored_nonces = RequestRandom::fetch_nonces(&requests, vrf_context).await?;
+
+    // bundles of the same address handed out but not confirmed yet are queued ahead
+    let (nonces, nonce_reservation) = RequestRandom::reserve_nonces(
+        &requests,
+        &stored_nonces,
+        &app_state.nonces,
+        vrf_context,
+        now,
+        window.execute_before,
+        nonce,
+    );
+    let queued = nonces != stored_nonces;
+    let seeds = RequestRandom::compute_seeds(&requests, &nonces, vrf_context.chain_id);
+
+    debug!("request_random: {:?}", requests);
+    debug!("seeds: {:?}", seeds);
+
+    // a single submit_random, ahead of the user's calls
+    let seed = RequestRandom::single_seed(&seeds)?;
+    let vrf_key = vrf_context.vrf_key.clone();
+    let vrf_account_address = vrf_context.vrf_account_address;
+    let submit_random_call = app_state
+        .prover
+        .run(move || build_submit_random_call(&vrf_key, vrf_account_address, seed))
+        .await??;
+    let calls = vec![
+        submit_random_call,
+        signed_outside_execution.build_execute_from_outside_call(),
+    ];
+
+    let outer_outside_execution = match config.version.resolve(&outside_execution) {
+        OutsideExecutionVersion::V3 => {
+            build_outside_execution_v3(calls, window, vrf_context.caller, nonce)
+        }
+        _ => build_outside_execution_v2(calls, window, vrf_context.caller, nonce),
+    };
+
+    let signature = sign_outside_execution(
+        &outer_outside_execution,
+        vrf_context.chain_id,
+        vrf_context.vrf_account_address.0,
+        vrf_context.vrf_signer.clone(),
+    )
+    .await;
+    let signed_outside_execution = SignedOutsideExecution {
+        address: vrf_context.vrf_account_address.0,
+        outside_execution: outer_outside_execution,
+        signature,
+    };
+
+    // the simulation runs on the current state, where a queued bundle is not valid yet
+    let simulation = if queued {
+        debug!("bundle queued behind pending bundles, skipping simulation");
+        SimulationMode::Disabled
+    } else {
+        config.simulation
+    };
+    let fee_estimate = simulate_call(
+        simulation,
+        vrf_context,
+        signed_outside_execution.build_execute_from_outside_call(),
+    )
+    .await?;
+
+    Ok(Bundle {
+        result: OutsideExecutionResult {
+            result: signed_outside_execution,
+            fee_estimate,
+        },
+        expires_at: window.execute_before,
+        nonce_reservation,
+    })
 }
 
-impl From<CairoShortStringToFeltError> for Errors {
-    fn from(value: CairoShortStringToFeltError) -> Self {
-        Errors::CairoShortStringToFeltError(value.to_string())
-    }
+pub fn build_outside_execution_v2(
+    calls: Vec<Call>,
+    window: ExecutionWindow,
+    caller: ContractAddress,
+    nonce: Felt,
+) -> OutsideExecution {
+    OutsideExecution::V2(OutsideExecutionV2 {
+        caller,
+        execute_after: window.execute_after,
+        execute_before: window.execute_before,
+        calls,
+        nonce,
+    })
 }
 
-impl From<url::ParseError> for Errors {
-    fn from(value: url::ParseError) -> Self {
-        Errors::UrlParserError(value.to_string())
-    }
+pub fn build_outside_execution_v3(
+    calls: Vec<Call>,
+    window: ExecutionWindow,
+    caller: ContractAddress,
+    nonce: Felt,
+) -> OutsideExecution {
+    OutsideExecution::V3(OutsideExecutionV3 {
+        caller,
+        execute_after: window.execute_after,
+        execute_before: window.execute_before,
+        calls,
+        // nonce as channel, first bit of the mask
+        nonce: (nonce, 1),
+    })
 }
 
 // curl -X POST -H "Content-Type: application/json" -d '{"request" : {"address":"0x111","outside_execution":{"caller":"0x414e595f43414c4c4552","calls":[{"calldata":["0x111","0x0","0x222"],"selector":"0x12a5a2e008479001f8f1a5f6c61ab6536d5ce46571fcdc0c9300dca0a9e532f","to":"0x888"},{"calldata":[],"selector":"0x1f9ca87172ecd8343d776bdd6024a4028f5596c76320882abd93e3bd1c724eb","to":"0x111"}],"execute_after":"0x0","execute_before":"0xb2d05e00","nonce":["0x564b73282b2fb5f201cf2070bf0ca2526871cb7daa06e0e805521ef5d907b33","0xa"]},"signature":["0x12345","0x67890"]}}' http://0.0.0.0:3000/outside_execution
@@ -199,9 +310,18 @@ impl From<url::ParseError> for Errors {
 #[cfg(test)]
 pub mod test {
     use crate::routes::outside_execution::{
-        types::{Call, OutsideExecution, OutsideExecutionV2, SignedOutsideExecution},
+        build_outside_execution_v2, build_outside_execution_v3, derive_outer_nonce,
+        types::{
+            get_window, Call, ExecutionWindow, OutsideExecution, OutsideExecutionV2,
+            SignedOutsideExecution,
+        },
         ANY_CALLER,
     };
+
+    const WINDOW: ExecutionWindow = ExecutionWindow {
+        execute_after: 0,
+        execute_before: 3000000000,
+    };
     use starknet::macros::{felt, selector};
 
     #[test]
@@ -237,4 +357,117 @@ pub mod test {
 
         println!("{serialized}");
     }
+
+    #[test]
+    fn build_outside_execution_versions() {
+        let calls = vec![Call {
+            to: felt!("0x111").into(),
+            selector: selector!("dice"),
+            calldata: vec![],
+        }];
+
+        match build_outside_execution_v2(calls.clone(), WINDOW, ANY_CALLER, felt!("0x1")) {
+            OutsideExecution::V2(v2) => {
+                assert_eq!(v2.caller, ANY_CALLER);
+                assert_eq!(v2.calls.len(), 1);
+            }
+            OutsideExecution::V3(_) => panic!("expected a V2 outside execution"),
+        }
+
+        match build_outside_execution_v3(calls, WINDOW, ANY_CALLER, felt!("0x1")) {
+            OutsideExecution::V3(v3) => {
+                assert_eq!(v3.caller, ANY_CALLER);
+                assert_eq!(v3.nonce.1, 1);
+                assert!(v3.execute_before > v3.execute_after);
+            }
+            OutsideExecution::V2(_) => panic!("expected a V3 outside execution"),
+        }
+    }
+
+    #[test]
+    fn execute_from_outside_selector_follows_version() {
+        let calls = vec![];
+
+        let v2 = SignedOutsideExecution {
+            address: felt!("0x111"),
+            outside_execution: build_outside_execution_v2(
+                calls.clone(),
+                WINDOW,
+                ANY_CALLER,
+                felt!("0x1"),
+            ),
+            signature: vec![],
+        };
+        assert_eq!(
+            v2.build_execute_from_outside_call().selector,
+            selector!("execute_from_outside_v2")
+        );
+
+        let v3 = SignedOutsideExecution {
+            address: felt!("0x111"),
+            outside_execution: build_outside_execution_v3(calls, WINDOW, ANY_CALLER, felt!("0x1")),
+            signature: vec![],
+        };
+        assert_eq!(
+            v3.build_execute_from_outside_call().selector,
+            selector!("execute_from_outside_v3")
+        );
+    }
+
+    #[test]
+    fn outer_window_is_clamped_to_inner_window() {
+        let window = ExecutionWindow::from_now(1000, 600);
+        assert_eq!(window.execute_after, 0);
+        assert_eq!(window.execute_before, 1600);
+
+        let inner = build_outside_execution_v2(
+            vec![],
+            ExecutionWindow {
+                execute_after: 900,
+                execute_before: 1300,
+            },
+            ANY_CALLER,
+            felt!("0x1"),
+        );
+        let clamped = window.clamp_to(get_window(&inner));
+        assert_eq!(
+            clamped,
+            ExecutionWindow {
+                execute_after: 900,
+                execute_before: 1300
+            }
+        );
+        assert!(!clamped.is_expired_at(1000));
+        assert!(clamped.is_expired_at(1300));
+
+        // a longer inner window keeps the configured expiry
+        assert_eq!(window.clamp_to(WINDOW), window);
+
+        // disjoint windows never open
+        let disjoint = window.clamp_to(ExecutionWindow {
+            execute_after: 1700,
+            execute_before: 1800,
+        });
+        assert!(disjoint.is_expired_at(1000));
+    }
+
+    #[test]
+    fn outer_nonce_is_derived_from_inner_execution() {
+        let inner = SignedOutsideExecution {
+            address: felt!("0x111"),
+            outside_execution: build_outside_execution_v2(vec![], WINDOW, ANY_CALLER, felt!("0x1")),
+            signature: vec![],
+        };
+        let chain_id = felt!("0x57505f4b4154414e41");
+        let nonce = derive_outer_nonce(chain_id, &inner);
+
+        assert_eq!(derive_outer_nonce(chain_id, &inner), nonce);
+        assert_ne!(derive_outer_nonce(felt!("0x534e5f4d41494e"), &inner), nonce);
+
+        let other = SignedOutsideExecution {
+            outside_execution: build_outside_execution_v2(vec![], WINDOW, ANY_CALLER, felt!("0x2")),
+            ..inner
+        };
+        assert_ne!(derive_outer_nonce(chain_id, &other), nonce);
+    }
 }
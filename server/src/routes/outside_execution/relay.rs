@@ -0,0 +1,86 @@
+//! Relayer mode: the server submits the `execute_from_outside` call itself with one
+//! of the funded accounts configured for the chain, and returns the transaction hash.
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::State;
+use axum::Json;
+use cainome_cairo_serde::ContractAddress;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use starknet::core::types::Felt;
+use starknet::core::utils::parse_cairo_short_string;
+use tracing::debug;
+
+use crate::routes::errors::Errors;
+use crate::routes::outside_execution::context::VrfContext;
+use crate::routes::outside_execution::{
+    build_bundle, derive_outer_nonce, OutsideExecutionRequest, OutsideExecutionResult,
+};
+use crate::state::SharedState;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayResult {
+    pub transaction_hash: Felt,
+    #[serde(flatten)]
+    pub bundle: OutsideExecutionResult,
+}
+
+pub async fn vrf_outside_execution_relay(
+    State(state): State<SharedState>,
+    payload: Result<Json<OutsideExecutionRequest>, JsonRejection>,
+) -> Result<Json<RelayResult>, Errors> {
+    let Json(payload) = payload?;
+    debug!("received relay payload {payload:?}");
+
+    if payload.context.executor.is_some() {
+        return Err(Errors::RequestContextError(
+            "executor can't be set in relayer mode".to_string(),
+        ));
+    }
+
+    let app_state = state.get().await;
+
+    let request = payload.request;
+    let mut vrf_context = VrfContext::build_from(payload.context, &app_state)?;
+    let chain_id = vrf_context.chain_id;
+    let nonce = derive_outer_nonce(chain_id, &request);
+
+    // retries get the transaction submitted for the first request
+    let app_state = &app_state;
+    let result = app_state
+        .relays
+        .get_or_try_insert(nonce, Utc::now().timestamp() as u64, move || async move {
+            let relayer = app_state
+                .chains
+                .get(chain_id)
+                .and_then(|chain| chain.relayers.pick())
+                .cloned()
+                .ok_or_else(|| {
+                    Errors::RelayerUnavailable(format!(
+                        "no relayer configured for chain {}",
+                        parse_cairo_short_string(&chain_id)
+                            .unwrap_or_else(|_| format!("{chain_id:#x}"))
+                    ))
+                })?;
+            vrf_context.caller = ContractAddress(relayer.address);
+
+            let bundle = build_bundle(app_state, &vrf_context, request, nonce).await?;
+            let call = bundle.result.result.build_execute_from_outside_call();
+            let transaction_hash = relayer
+                .submit(&vrf_context.provider, chain_id, call)
+                .await?;
+            bundle.track(app_state, &vrf_context, nonce, Some(transaction_hash));
+
+            let (bundle, expires_at) = bundle.keep();
+            Ok::<_, Errors>((
+                RelayResult {
+                    transaction_hash,
+                    bundle,
+                },
+                expires_at,
+            ))
+        })
+        .await?;
+
+    Ok(Json(result))
+}
@@ -0,0 +1,185 @@
+// Guards the `rpc_url` supplied by clients, the server calls it from inside our
+// network and trusts it for the VRF nonce used to compute seeds.
+
+use starknet::providers::Url;
+
+use crate::config::ClientRpcUrlPolicy;
+use crate::routes::errors::Errors;
+use crate::state::chains::ChainEntry;
+
+/// RPC selected for a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedRpcUrl {
+    pub url: Url,
+    /// Whether the URL was supplied by the client, in which case its chain id must be checked.
+    pub from_client: bool,
+}
+
+pub fn resolve_rpc_url(
+    policy: ClientRpcUrlPolicy,
+    chain: &ChainEntry,
+    client_rpc_url: Option<Url>,
+) -> Result<ResolvedRpcUrl, Errors> {
+    let client_rpc_url = match policy {
+        ClientRpcUrlPolicy::Allowlist => client_rpc_url,
+        ClientRpcUrlPolicy::Ignore => None,
+    };
+
+    match client_rpc_url {
+        Some(url) if chain.rpc_url.as_ref() == Some(&url) => Ok(ResolvedRpcUrl {
+            url,
+            from_client: false,
+        }),
+        Some(url) => {
+            if !is_allowed(&chain.allowed_rpc_urls, &url) {
+                return Err(Errors::RpcUrlRejected(format!(
+                    "{url} is not allowed for chain {}",
+                    chain.name
+                )));
+            }
+
+            Ok(ResolvedRpcUrl {
+                url,
+                from_client: true,
+            })
+        }
+        None => chain
+            .rpc_url
+            .clone()
+            .map(|url| ResolvedRpcUrl {
+                url,
+                from_client: false,
+            })
+            .ok_or_else(|| Errors::RequestContextError("no rpc_url provided".to_owned())),
+    }
+}
+
+/// Entries are either hosts, optionally with a port, or URL prefixes.
+fn is_allowed(allowed_rpc_urls: &[String], url: &Url) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+
+    allowed_rpc_urls.iter().any(|allowed| {
+        if allowed.contains("://") {
+            let Ok(allowed) = Url::parse(allowed) else {
+                return false;
+            };
+
+            allowed.scheme() == url.scheme()
+                && allowed.host_str() == Some(host)
+                && allowed.port_or_known_default() == url.port_or_known_default()
+                && is_path_prefix(allowed.path(), url.path())
+        } else {
+            match allowed.split_once(':') {
+                Some((allowed_host, allowed_port)) => {
+                    allowed_host.eq_ignore_ascii_case(host)
+                        && url.port_or_known_default().map(|port| port.to_string())
+                            == Some(allowed_port.to_string())
+                }
+                None => allowed.eq_ignore_ascii_case(host),
+            }
+        }
+    })
+}
+
+/// Whether `path` is `prefix` or below it, `/starknet` matches `/starknet/mainnet`
+/// but not `/starknet-evil`.
+fn is_path_prefix(prefix: &str, path: &str) -> bool {
+    match path.strip_prefix(prefix) {
+        Some(rest) => prefix.ends_with('/') || rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::relayers::RelayerPool;
+    use crate::vrf::VrfKey;
+    use cainome_cairo_serde::ContractAddress;
+    use starknet::macros::felt;
+    use starknet::signers::{LocalWallet, SigningKey};
+
+    fn chain() -> ChainEntry {
+        ChainEntry {
+            name: "katana".into(),
+            chain_id: felt!("0x57505f4b4154414e41"),
+            rpc_url: Some(Url::parse("http://localhost:5050").unwrap()),
+            allowed_rpc_urls: vec![
+                "rpc.example.com".into(),
+                "localhost:6060".into(),
+                "https://api.example.com/x/starknet".into(),
+            ],
+            allowed_executors: vec![],
+            relayers: RelayerPool::default(),
+            vrf_account_address: ContractAddress(felt!("0x123")),
+            vrf_signer: LocalWallet::from(SigningKey::from_secret_scalar(felt!("0x111"))),
+            vrf_key: VrfKey::from_secret_key(felt!("0x1a4")),
+        }
+    }
+
+    fn resolve(policy: ClientRpcUrlPolicy, url: Option<&str>) -> Result<ResolvedRpcUrl, Errors> {
+        resolve_rpc_url(policy, &chain(), url.map(|url| Url::parse(url).unwrap()))
+    }
+
+    #[test]
+    fn falls_back_to_chain_rpc_url() {
+        let resolved = resolve(ClientRpcUrlPolicy::Allowlist, None).unwrap();
+        assert_eq!(resolved.url.as_str(), "http://localhost:5050/");
+        assert!(!resolved.from_client);
+    }
+
+    #[test]
+    fn accepts_allowlisted_urls() {
+        for url in [
+            "https://rpc.example.com/rpc/v0_9",
+            "http://localhost:6060",
+            "https://api.example.com/x/starknet",
+            "https://api.example.com/x/starknet/mainnet",
+        ] {
+            let resolved = resolve(ClientRpcUrlPolicy::Allowlist, Some(url)).unwrap();
+            assert!(resolved.from_client, "{url} should be allowed");
+        }
+
+        let resolved =
+            resolve(ClientRpcUrlPolicy::Allowlist, Some("http://localhost:5050")).unwrap();
+        assert!(!resolved.from_client);
+    }
+
+    #[test]
+    fn rejects_other_urls() {
+        for url in [
+            "http://169.254.169.254/latest/meta-data",
+            "http://localhost:7070",
+            "https://api.example.com/x/other",
+            "https://api.example.com/x/starknet-evil/mainnet",
+            "https://api.example.com/x/starknetevil",
+            "https://rpc.example.com.evil.io",
+            "file:///etc/passwd",
+        ] {
+            assert!(
+                matches!(
+                    resolve(ClientRpcUrlPolicy::Allowlist, Some(url)),
+                    Err(Errors::RpcUrlRejected(_))
+                ),
+                "{url} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn ignore_policy_uses_chain_rpc_url() {
+        let resolved = resolve(
+            ClientRpcUrlPolicy::Ignore,
+            Some("http://169.254.169.254/latest/meta-data"),
+        )
+        .unwrap();
+        assert_eq!(resolved.url.as_str(), "http://localhost:5050/");
+        assert!(!resolved.from_client);
+    }
+}
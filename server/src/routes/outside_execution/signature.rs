@@ -3,10 +3,20 @@
 // https://github.com/cartridge-gg/controller-rs/blob/main/account_sdk/src/account/outside_execution_v2.rs
 
 use account_sdk::hash::MessageHashRev1;
+use starknet::core::types::{BlockId, BlockTag, FunctionCall, StarknetError};
+use starknet::macros::{selector, short_string};
+use starknet::providers::{Provider, ProviderError};
 use starknet::signers::{LocalWallet, Signer};
 use starknet_crypto::Felt;
+use std::time::Instant;
 
-use crate::routes::outside_execution::types::OutsideExecution;
+use crate::metrics;
+use crate::routes::errors::Errors;
+use crate::routes::outside_execution::context::VrfContext;
+use crate::routes::outside_execution::types::{OutsideExecution, SignedOutsideExecution};
+
+/// SRC6 `is_valid_signature` success value.
+const VALIDATED: Felt = short_string!("VALID");
 
 pub async fn sign_outside_execution(
     outside_execution: &OutsideExecution,
@@ -21,12 +31,66 @@ pub async fn sign_outside_execution(
     vec![signature.r, signature.s]
 }
 
+/// Checks the user's signature with `is_valid_signature` on their account.
+pub async fn verify_outside_execution_signature(
+    signed_outside_execution: &SignedOutsideExecution,
+    vrf_context: &VrfContext,
+) -> Result<(), Errors> {
+    let hash = signed_outside_execution
+        .outside_execution
+        .get_message_hash_rev_1(vrf_context.chain_id, signed_outside_execution.address);
+
+    let mut calldata = vec![hash, signed_outside_execution.signature.len().into()];
+    calldata.extend(signed_outside_execution.signature.iter().copied());
+
+    let start = Instant::now();
+    let result = vrf_context
+        .provider
+        .call(
+            FunctionCall {
+                contract_address: signed_outside_execution.address,
+                entry_point_selector: selector!("is_valid_signature"),
+                calldata,
+            },
+            BlockId::Tag(BlockTag::PreConfirmed),
+        )
+        .await;
+    metrics::record_rpc(
+        vrf_context.chain_id,
+        "is_valid_signature",
+        start,
+        !matches!(&result, Err(error) if metrics::is_rpc_failure(error)),
+    );
+
+    match result {
+        Ok(result) if is_validated(&result) => Ok(()),
+        Ok(_) => Err(Errors::InvalidSignature(format!(
+            "signature rejected by account {:#x}",
+            signed_outside_execution.address
+        ))),
+        Err(ProviderError::StarknetError(
+            StarknetError::ContractNotFound
+            | StarknetError::EntrypointNotFound
+            | StarknetError::ContractError(_),
+        )) => Err(Errors::InvalidSignature(format!(
+            "is_valid_signature failed on account {:#x}",
+            signed_outside_execution.address
+        ))),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Accounts return `'VALID'`, legacy ones return `1`.
+fn is_validated(result: &[Felt]) -> bool {
+    matches!(result, [value] if *value == VALIDATED || *value == Felt::ONE)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::routes::outside_execution::types::{Call, OutsideExecutionV2};
+    use crate::routes::outside_execution::types::{Call, OutsideExecutionV2, OutsideExecutionV3};
     use cainome_cairo_serde::ContractAddress;
-    use starknet::macros::{felt, selector};
+    use starknet::macros::felt;
     use starknet::signers::SigningKey;
 
     const TEST_CHAIN_ID: Felt = felt!("0x57505f4b4154414e41"); // WP_KATANA
@@ -66,6 +130,16 @@ mod tests {
         })
     }
 
+    fn test_outside_execution_v3() -> OutsideExecution {
+        OutsideExecution::V3(OutsideExecutionV3 {
+            caller: TEST_CALLER,
+            nonce: (felt!("0x1"), 1),
+            execute_after: 0,
+            execute_before: 3000000000,
+            calls: test_calls(),
+        })
+    }
+
     #[tokio::test]
     async fn sign_v2_produces_valid_signature() {
         let oe = test_outside_execution_v2();
@@ -82,4 +156,34 @@ mod tests {
             "V2 signature should be valid"
         );
     }
+
+    #[test]
+    fn is_valid_signature_results() {
+        assert!(is_validated(&[VALIDATED]));
+        assert!(is_validated(&[Felt::ONE]));
+        assert!(!is_validated(&[Felt::ZERO]));
+        assert!(!is_validated(&[]));
+        assert!(!is_validated(&[VALIDATED, Felt::ONE]));
+    }
+
+    #[tokio::test]
+    async fn sign_v3_produces_valid_signature() {
+        let oe = test_outside_execution_v3();
+        let sig =
+            sign_outside_execution(&oe, TEST_CHAIN_ID, TEST_SIGNER_ADDRESS, test_signer()).await;
+
+        assert_eq!(sig.len(), 2, "signature should have r and s components");
+
+        let hash = oe.get_message_hash_rev_1(TEST_CHAIN_ID, TEST_SIGNER_ADDRESS);
+        let public_key = test_signing_key().verifying_key().scalar();
+
+        assert!(
+            starknet_crypto::verify(&public_key, &hash, &sig[0], &sig[1]).unwrap(),
+            "V3 signature should be valid"
+        );
+
+        let v2_hash =
+            test_outside_execution_v2().get_message_hash_rev_1(TEST_CHAIN_ID, TEST_SIGNER_ADDRESS);
+        assert_ne!(hash, v2_hash, "V2 and V3 hashes should differ");
+    }
 }
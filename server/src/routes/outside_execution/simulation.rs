@@ -0,0 +1,124 @@
+// Pre-flight check of the `execute_from_outside` call returned to the client, the
+// VRF account is used as sender since its outer outside execution accepts any caller.
+// Outside executions restricted to an executor are simulated from the executor,
+// skipping its validation since the server can't sign for it.
+
+use starknet::{
+    accounts::{Account, AccountError, ExecutionEncoding, SingleOwnerAccount},
+    core::types::{
+        BlockId, BlockTag, ContractExecutionError, ExecuteInvocation, FeeEstimate, StarknetError,
+        TransactionTrace,
+    },
+    providers::ProviderError,
+    signers::local_wallet::SignError,
+};
+
+use std::time::Instant;
+
+use crate::config::SimulationMode;
+use crate::metrics;
+use crate::routes::errors::Errors;
+use crate::routes::outside_execution::{context::VrfContext, types::Call, ANY_CALLER};
+
+/// Runs `call` against the chain provider, returns the fee estimate or the revert reason.
+pub async fn simulate_call(
+    mode: SimulationMode,
+    vrf_context: &VrfContext,
+    call: Call,
+) -> Result<Option<FeeEstimate>, Errors> {
+    if mode == SimulationMode::Disabled {
+        return Ok(None);
+    }
+
+    let (sender, mode) = if vrf_context.caller == ANY_CALLER {
+        (vrf_context.vrf_account_address.0, mode)
+    } else {
+        (vrf_context.caller.0, SimulationMode::Simulate)
+    };
+
+    let mut account = SingleOwnerAccount::new(
+        vrf_context.provider.clone(),
+        vrf_context.vrf_signer.clone(),
+        sender,
+        vrf_context.chain_id,
+        ExecutionEncoding::New,
+    );
+    account.set_block_id(BlockId::Tag(BlockTag::PreConfirmed));
+
+    let execution = account.execute_v3(vec![call.into()]);
+
+    let start = Instant::now();
+    let fee_estimate = match mode {
+        SimulationMode::Simulate => {
+            let simulated = execution.simulate(true, true).await;
+            record_simulation(vrf_context, "simulate_transactions", start, &simulated);
+            let simulated = simulated.map_err(simulation_error)?;
+
+            if let TransactionTrace::Invoke(trace) = &simulated.transaction_trace {
+                if let ExecuteInvocation::Reverted(reverted) = &trace.execute_invocation {
+                    return Err(Errors::SimulationReverted(reverted.revert_reason.clone()));
+                }
+            }
+
+            simulated.fee_estimation
+        }
+        SimulationMode::EstimateFee => {
+            let estimated = execution.estimate_fee().await;
+            record_simulation(vrf_context, "estimate_fee", start, &estimated);
+            estimated.map_err(simulation_error)?
+        }
+        SimulationMode::Disabled => unreachable!(),
+    };
+
+    Ok(Some(fee_estimate))
+}
+
+fn record_simulation<T>(
+    vrf_context: &VrfContext,
+    method: &'static str,
+    start: Instant,
+    result: &Result<T, AccountError<SignError>>,
+) {
+    let failed =
+        matches!(result, Err(AccountError::Provider(error)) if metrics::is_rpc_failure(error));
+    metrics::record_rpc(vrf_context.chain_id, method, start, !failed);
+}
+
+fn simulation_error(error: AccountError<SignError>) -> Errors {
+    match error {
+        AccountError::Provider(ProviderError::StarknetError(
+            StarknetError::TransactionExecutionError(data),
+        )) => Errors::SimulationReverted(revert_reason(&data.execution_error)),
+        AccountError::Provider(error) => error.into(),
+        error => Errors::ProviderError(error.to_string()),
+    }
+}
+
+/// Returns the innermost error message of a contract execution error.
+pub(crate) fn revert_reason(error: &ContractExecutionError) -> String {
+    match error {
+        ContractExecutionError::Message(message) => message.clone(),
+        ContractExecutionError::Nested(inner) => revert_reason(&inner.error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use starknet::core::types::InnerContractExecutionError;
+    use starknet::macros::{felt, selector};
+
+    #[test]
+    fn revert_reason_unwraps_nested_errors() {
+        let error = ContractExecutionError::Nested(InnerContractExecutionError {
+            contract_address: felt!("0x111"),
+            class_hash: felt!("0x222"),
+            selector: selector!("execute_from_outside_v2"),
+            error: Box::new(ContractExecutionError::Message(
+                "SRC9: now >= execute_before".into(),
+            )),
+        });
+
+        assert_eq!(revert_reason(&error), "SRC9: now >= execute_before");
+    }
+}
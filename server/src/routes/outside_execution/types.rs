@@ -17,6 +17,51 @@ pub fn get_calls(outside_execution: &OutsideExecution) -> &[Call] {
     }
 }
 
+/// Validity window of an outside execution, in seconds since the epoch. Accounts
+/// only execute it when `execute_after < now < execute_before`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutionWindow {
+    pub execute_after: u64,
+    pub execute_before: u64,
+}
+
+impl ExecutionWindow {
+    /// Window closing `expiry` seconds after `now`.
+    pub fn from_now(now: u64, expiry: u64) -> Self {
+        ExecutionWindow {
+            execute_after: 0,
+            execute_before: now + expiry,
+        }
+    }
+
+    /// Restricts the window to `other`.
+    pub fn clamp_to(self, other: ExecutionWindow) -> Self {
+        ExecutionWindow {
+            execute_after: self.execute_after.max(other.execute_after),
+            execute_before: self.execute_before.min(other.execute_before),
+        }
+    }
+
+    /// Whether the window can still open at or after `now`.
+    pub fn is_expired_at(&self, now: u64) -> bool {
+        self.execute_before <= now || self.execute_after + 1 >= self.execute_before
+    }
+}
+
+/// Returns the validity window of an outside execution.
+pub fn get_window(outside_execution: &OutsideExecution) -> ExecutionWindow {
+    match outside_execution {
+        OutsideExecution::V2(v2) => ExecutionWindow {
+            execute_after: v2.execute_after,
+            execute_before: v2.execute_before,
+        },
+        OutsideExecution::V3(v3) => ExecutionWindow {
+            execute_after: v3.execute_after,
+            execute_before: v3.execute_before,
+        },
+    }
+}
+
 /// Returns the appropriate `execute_from_outside` selector for the version.
 pub fn get_selector(outside_execution: &OutsideExecution) -> Felt {
     match outside_execution {
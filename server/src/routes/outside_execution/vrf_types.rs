@@ -2,20 +2,23 @@
 
 use cainome::cairo_serde_derive::CairoSerde;
 use cainome_cairo_serde::ContractAddress;
-use num::{BigInt, Num};
 use serde::{Deserialize, Serialize};
-use stark_vrf::{BaseField, StarkVRF};
 use starknet::{core::types::BlockId, macros::selector, providers::Provider};
 use starknet_crypto::{pedersen_hash, poseidon_hash_many, Felt};
-use std::str::FromStr;
+use std::collections::HashMap;
+use std::time::Instant;
 
 use crate::{
-    routes::outside_execution::{
-        context::VrfContext,
-        types::{get_calls, Call, OutsideExecution},
-        Errors,
+    metrics,
+    routes::{
+        errors::Errors,
+        outside_execution::{
+            context::VrfContext,
+            types::{get_calls, Call, OutsideExecution},
+        },
     },
-    utils::format_felt,
+    state::nonces::{NonceKey, NonceReservation, NonceReservations},
+    vrf::{base_field_to_felt, felt_to_base_field, scalar_to_felt, VrfKey},
 };
 
 #[derive(Clone, CairoSerde, Serialize, Deserialize, Debug)]
@@ -31,78 +34,309 @@ pub struct RequestRandom {
 }
 
 impl RequestRandom {
-    pub fn get_request_random_call(outside_execution: &OutsideExecution) -> (Option<Call>, usize) {
+    /// Returns every `request_random` call with its position in the outside execution.
+    pub fn get_request_random_calls(outside_execution: &OutsideExecution) -> Vec<(usize, Call)> {
+        get_calls(outside_execution)
+            .iter()
+            .enumerate()
+            .filter(|(_, call)| call.selector == selector!("request_random"))
+            .map(|(position, call)| (position, call.clone()))
+            .collect()
+    }
+
+    /// Ensures `caller` is the contract of a call following the `request_random`
+    /// call at `position`, as it is the one consuming the random value.
+    pub fn ensure_caller_called_after(
+        &self,
+        outside_execution: &OutsideExecution,
+        position: usize,
+    ) -> Result<(), Errors> {
         let calls = get_calls(outside_execution);
 
-        let position = calls
+        if calls[position + 1..]
             .iter()
-            .position(|call| call.selector == selector!("request_random"));
-
-        match position {
-            Some(position) => (Option::Some(calls[position].clone()), position),
-            None => (Option::None, 0),
+            .any(|call| call.to == self.caller)
+        {
+            return Ok(());
         }
+
+        Err(Errors::RequestRandomCallerMismatch(format!(
+            "no call to {:#x} after request_random",
+            self.caller.0
+        )))
     }
 
-    pub async fn compute_seed(
-        self: &RequestRandom,
+    /// Reads the current `VrfProvider_nonces` of every `Source::Nonce` address in `requests`.
+    pub async fn fetch_nonces(
+        requests: &[RequestRandom],
         vrf_context: &VrfContext,
-    ) -> Result<Felt, Errors> {
-        let caller = self.caller.0;
+    ) -> Result<HashMap<Felt, Felt>, Errors> {
+        let mut nonces = HashMap::new();
 
-        let seed = match self.source {
-            Source::Nonce(contract_address) => {
-                let provider = vrf_context.provider.clone();
-                let vrf_account_address = vrf_context.vrf_account_address;
+        for request in requests {
+            if let Source::Nonce(contract_address) = request.source {
+                if nonces.contains_key(&contract_address.0) {
+                    continue;
+                }
 
                 let key = pedersen_hash(&selector!("VrfProvider_nonces"), &contract_address.0);
-                let nonce = provider
+                let start = Instant::now();
+                let nonce = vrf_context
+                    .provider
                     .get_storage_at(
-                        vrf_account_address.0,
+                        vrf_context.vrf_account_address.0,
                         key,
                         BlockId::Tag(starknet::core::types::BlockTag::PreConfirmed),
                     )
-                    .await?;
+                    .await;
+                metrics::record_rpc(vrf_context.chain_id, "get_storage_at", start, nonce.is_ok());
+                let nonce = nonce?;
 
-                poseidon_hash_many(&[nonce, contract_address.0, caller, vrf_context.chain_id])
+                nonces.insert(contract_address.0, nonce);
             }
-            Source::Salt(felt) => poseidon_hash_many(&[felt, caller, vrf_context.chain_id]),
+        }
+
+        Ok(nonces)
+    }
+
+    /// Returns the nonces left by the bundles still pending on top of the `stored` ones,
+    /// and reserves the nonce incremented by the bundle of `outer_nonce` until `expires_at`.
+    ///
+    /// Only the first `consume_random` of a transaction increments a nonce, so a
+    /// reservation is only made when the first request is a `Source::Nonce`.
+    pub fn reserve_nonces(
+        requests: &[RequestRandom],
+        stored: &HashMap<Felt, Felt>,
+        reservations: &NonceReservations,
+        vrf_context: &VrfContext,
+        now: u64,
+        expires_at: u64,
+        outer_nonce: Felt,
+    ) -> (HashMap<Felt, Felt>, Option<NonceReservation>) {
+        let key = |address| {
+            NonceKey::new(
+                vrf_context.chain_id,
+                vrf_context.vrf_account_address.0,
+                address,
+            )
         };
 
-        Ok(seed)
+        let reservation = match requests.first().map(|request| &request.source) {
+            Some(Source::Nonce(contract_address)) => Some(reservations.reserve(
+                key(contract_address.0),
+                stored.get(&contract_address.0).copied().unwrap_or_default(),
+                now,
+                expires_at,
+                outer_nonce,
+            )),
+            _ => None,
+        };
+
+        let nonces = stored
+            .iter()
+            .map(|(address, stored)| {
+                let nonce = match &reservation {
+                    Some(reservation) if reservation.key().address == *address => {
+                        reservation.nonce()
+                    }
+                    _ => reservations.pending_nonce(key(*address), *stored, now),
+                };
+                (*address, nonce)
+            })
+            .collect();
+
+        (nonces, reservation)
+    }
+
+    /// Computes the seed of each request, in order, from the stored `nonces`.
+    ///
+    /// Mirrors `_get_seed` in the VRF account: every `consume_random` of the
+    /// transaction shares one consume count and only the first one increments the
+    /// nonce, later `Source::Nonce` consumes use the stored nonce minus one.
+    /// Requests are expected to be consumed in the order they were made.
+    pub fn compute_seeds(
+        requests: &[RequestRandom],
+        nonces: &HashMap<Felt, Felt>,
+        chain_id: Felt,
+    ) -> Vec<Felt> {
+        let mut nonces = nonces.clone();
+
+        requests
+            .iter()
+            .enumerate()
+            .map(|(consume_count, request)| {
+                let caller = request.caller.0;
+
+                match request.source {
+                    Source::Nonce(contract_address) => {
+                        let stored = nonces.get(&contract_address.0).copied().unwrap_or_default();
+
+                        let nonce = if consume_count == 0 {
+                            // only the first consume_random increments the nonce
+                            nonces.insert(contract_address.0, stored + Felt::ONE);
+                            stored
+                        } else {
+                            stored - Felt::ONE
+                        };
+
+                        poseidon_hash_many(&[nonce, contract_address.0, caller, chain_id])
+                    }
+                    Source::Salt(felt) => poseidon_hash_many(&[felt, caller, chain_id]),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the seed shared by every request.
+    ///
+    /// The VRF account only asserts and clears the seed of the last `submit_random` of
+    /// a transaction, the random value of an earlier seed would stay consumable without
+    /// a new proof. Bundles are limited to one distinct seed until every submitted seed
+    /// is cleared.
+    pub fn single_seed(seeds: &[Felt]) -> Result<Felt, Errors> {
+        match seeds {
+            [] => Err(Errors::NoRequestRandom),
+            [seed, rest @ ..] if rest.iter().all(|other| other == seed) => Ok(*seed),
+            _ => {
+                let mut distinct = seeds.to_vec();
+                distinct.sort();
+                distinct.dedup();
+                Err(Errors::MultipleSeeds(format!(
+                    "{} request_random calls use {} distinct seeds, at most one is supported",
+                    seeds.len(),
+                    distinct.len()
+                )))
+            }
+        }
     }
 }
 
-pub fn build_submit_random_call(vrf_context: &VrfContext, seed: Felt) -> Call {
-    let seed_vec: Vec<_> = [seed]
-        .iter()
-        .map(|x| {
-            let x = x.to_hex_string();
-            let dec_string = BigInt::from_str_radix(&x[2..], 16).unwrap().to_string();
-            BaseField::from_str(&dec_string).unwrap()
-        })
-        .collect();
-
-    let ecvrf = StarkVRF::new(vrf_context.public_key).unwrap();
-    let proof = ecvrf
-        .prove(
-            &vrf_context.secret_key.parse().unwrap(),
-            seed_vec.as_slice(),
-        )
-        .unwrap();
-    let sqrt_ratio_hint = ecvrf.hash_to_sqrt_ratio_hint(seed_vec.as_slice());
-    // let rnd = ecvrf.proof_to_hash(&proof).unwrap();
-
-    Call {
-        to: vrf_context.vrf_account_address,
+pub fn build_submit_random_call(
+    vrf_key: &VrfKey,
+    vrf_account_address: ContractAddress,
+    seed: Felt,
+) -> Result<Call, Errors> {
+    let proof = vrf_key
+        .prove(&[felt_to_base_field(seed)])
+        .map_err(|e| Errors::Internal(e.to_string()))?;
+
+    Ok(Call {
+        to: vrf_account_address,
         selector: selector!("submit_random"),
         calldata: vec![
             seed,
-            format_felt(proof.0.x),
-            format_felt(proof.0.y),
-            format_felt(proof.1),
-            format_felt(proof.2),
-            format_felt(sqrt_ratio_hint),
+            base_field_to_felt(proof.gamma.x),
+            base_field_to_felt(proof.gamma.y),
+            scalar_to_felt(proof.c),
+            scalar_to_felt(proof.s),
+            base_field_to_felt(proof.sqrt_ratio),
         ],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use starknet::macros::felt;
+
+    const CHAIN_ID: Felt = felt!("0x57505f4b4154414e41"); // WP_KATANA
+    const CALLER: ContractAddress = ContractAddress(felt!("0x111"));
+    const PLAYER_A: ContractAddress = ContractAddress(felt!("0xa"));
+    const PLAYER_B: ContractAddress = ContractAddress(felt!("0xb"));
+
+    fn nonce_request(address: ContractAddress) -> RequestRandom {
+        RequestRandom {
+            caller: CALLER,
+            source: Source::Nonce(address),
+        }
+    }
+
+    fn nonce_seed(nonce: u64, address: ContractAddress) -> Felt {
+        poseidon_hash_many(&[Felt::from(nonce), address.0, CALLER.0, CHAIN_ID])
+    }
+
+    fn stored_nonces() -> HashMap<Felt, Felt> {
+        HashMap::from([
+            (PLAYER_A.0, Felt::from(5_u64)),
+            (PLAYER_B.0, Felt::from(3_u64)),
+        ])
+    }
+
+    #[test]
+    fn single_nonce_request_uses_stored_nonce() {
+        let seeds =
+            RequestRandom::compute_seeds(&[nonce_request(PLAYER_A)], &stored_nonces(), CHAIN_ID);
+
+        assert_eq!(seeds, vec![nonce_seed(5, PLAYER_A)]);
+    }
+
+    #[test]
+    fn repeated_nonce_request_reuses_seed() {
+        let seeds = RequestRandom::compute_seeds(
+            &[nonce_request(PLAYER_A), nonce_request(PLAYER_A)],
+            &stored_nonces(),
+            CHAIN_ID,
+        );
+
+        assert_eq!(
+            seeds,
+            vec![nonce_seed(5, PLAYER_A), nonce_seed(5, PLAYER_A)]
+        );
+    }
+
+    #[test]
+    fn later_nonce_requests_use_previous_nonce() {
+        let seeds = RequestRandom::compute_seeds(
+            &[nonce_request(PLAYER_A), nonce_request(PLAYER_B)],
+            &stored_nonces(),
+            CHAIN_ID,
+        );
+
+        assert_eq!(
+            seeds,
+            vec![nonce_seed(5, PLAYER_A), nonce_seed(2, PLAYER_B)]
+        );
+    }
+
+    #[test]
+    fn salt_then_nonce_request() {
+        let salt = RequestRandom {
+            caller: CALLER,
+            source: Source::Salt(felt!("0x5a17")),
+        };
+
+        let seeds = RequestRandom::compute_seeds(
+            &[salt, nonce_request(PLAYER_A)],
+            &stored_nonces(),
+            CHAIN_ID,
+        );
+
+        assert_eq!(
+            seeds,
+            vec![
+                poseidon_hash_many(&[felt!("0x5a17"), CALLER.0, CHAIN_ID]),
+                nonce_seed(4, PLAYER_A)
+            ]
+        );
+    }
+
+    #[test]
+    fn bundles_have_a_single_seed() {
+        let seed = nonce_seed(5, PLAYER_A);
+        assert_eq!(RequestRandom::single_seed(&[seed, seed]).unwrap(), seed);
+
+        let seeds = RequestRandom::compute_seeds(
+            &[nonce_request(PLAYER_A), nonce_request(PLAYER_B)],
+            &stored_nonces(),
+            CHAIN_ID,
+        );
+        assert!(matches!(
+            RequestRandom::single_seed(&seeds),
+            Err(Errors::MultipleSeeds(_))
+        ));
+        assert!(matches!(
+            RequestRandom::single_seed(&[]),
+            Err(Errors::NoRequestRandom)
+        ));
     }
 }
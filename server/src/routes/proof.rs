@@ -1,64 +1,257 @@
+use crate::metrics;
 use crate::oracle::{StarkVrfProof, StarkVrfRequest};
+use crate::routes::errors::{ErrorBody, Errors};
 use crate::state::SharedState;
-use crate::utils::format;
+use crate::vrf::{base_field_to_felt, scalar_to_felt, VrfKey};
+use ark_ff::PrimeField;
+use axum::extract::rejection::JsonRejection;
 use axum::extract::State;
 use axum::Json;
-use num::{BigInt, Num};
+use num::{BigUint, Num};
 use serde::{Deserialize, Serialize};
-use stark_vrf::{BaseField, StarkVRF};
-use std::str::FromStr;
+use stark_vrf::BaseField;
 use tracing::debug;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JsonResult {
-    result: StarkVrfProof,
+    pub result: StarkVrfProof,
+}
+
+/// Stark field prime, `2^251 + 17 * 2^192 + 1`.
+pub const FIELD_PRIME: &str = "800000000000011000000000000000000000000000000000000000000000001";
+
+/// Parses the elements of a seed, errors name the first invalid element.
+pub fn parse_seed(seed: &[String]) -> Result<Vec<BaseField>, Errors> {
+    if seed.is_empty() {
+        return Err(Errors::InvalidSeed("seed must not be empty".to_string()));
+    }
+
+    seed.iter()
+        .enumerate()
+        .map(|(index, element)| {
+            let value = parse_seed_element(element)
+                .map_err(|e| Errors::InvalidSeed(format!("seed[{index}] `{element}`: {e}")))?;
+
+            // in range, see `parse_seed_element`
+            Ok(BaseField::from_be_bytes_mod_order(&value.to_bytes_be()))
+        })
+        .collect()
+}
+
+/// Parses a seed element given as hex, with or without `0x` prefix, or decimal.
+/// Bare digits are always decimal (`100` is one hundred), unprefixed elements are
+/// hex when they contain a letter (`ff`, `1a4`).
+fn parse_seed_element(element: &str) -> Result<BigUint, String> {
+    let element = element.trim();
+
+    let value = match element
+        .strip_prefix("0x")
+        .or_else(|| element.strip_prefix("0X"))
+    {
+        Some(hex) => BigUint::from_str_radix(hex, 16).ok(),
+        None if element.chars().all(|c| c.is_ascii_digit()) => {
+            BigUint::from_str_radix(element, 10).ok()
+        }
+        None => BigUint::from_str_radix(element, 16).ok(),
+    }
+    .ok_or_else(|| "must be a hex or decimal number".to_string())?;
+
+    let prime = BigUint::from_str_radix(FIELD_PRIME, 16).expect("valid field prime");
+    if value >= prime {
+        return Err("must be lower than the Stark field prime".to_string());
+    }
+
+    Ok(value)
+}
+
+/// Proves `seed` with the VRF secret key.
+pub fn prove(vrf_key: &VrfKey, seed: &[BaseField]) -> Result<StarkVrfProof, Errors> {
+    let proof = vrf_key
+        .prove(seed)
+        .map_err(|e| Errors::Internal(e.to_string()))?;
+
+    debug!("proof gamma: {}", proof.gamma);
+    debug!("proof c: {}", proof.c);
+    debug!("proof s: {}", proof.s);
+    debug!("proof verify hint: {}", proof.sqrt_ratio);
+
+    Ok(StarkVrfProof {
+        gamma_x: base_field_to_felt(proof.gamma.x).to_hex_string(),
+        gamma_y: base_field_to_felt(proof.gamma.y).to_hex_string(),
+        c: scalar_to_felt(proof.c).to_hex_string(),
+        s: scalar_to_felt(proof.s).to_hex_string(),
+        sqrt_ratio: base_field_to_felt(proof.sqrt_ratio).to_hex_string(),
+        rnd: base_field_to_felt(proof.rnd).to_hex_string(),
+    })
 }
 
 // curl -X POST -H "Content-Type: application/json" -d '{"seed": ["0x5db4e1c9bd8b0898674bf96f79e8fbffa3fe6d70a4597683c4dba2f0930dc45"]}' http://0.0.0.0:3000/proof
 
 pub async fn vrf_proof(
     State(state): State<SharedState>,
-    Json(payload): Json<StarkVrfRequest>,
-) -> Json<JsonResult> {
+    payload: Result<Json<StarkVrfRequest>, JsonRejection>,
+) -> Result<Json<JsonResult>, Errors> {
+    let Json(payload) = payload?;
     debug!("received payload {payload:?}");
-    let secret_key = &state.read().unwrap().secret_key;
-    let public_key = state.read().unwrap().public_key;
-
-    debug!("public key {public_key}");
-
-    let seed: Vec<_> = payload
-        .seed
-        .iter()
-        .map(|x| {
-            let dec_string = BigInt::from_str_radix(&x[2..], 16).unwrap().to_string();
-            debug!("seed string {dec_string}");
-            BaseField::from_str(&dec_string).unwrap()
-        })
-        .collect();
+    let app_state = state.get().await;
+    let vrf_key = app_state.vrf_key;
 
-    let ecvrf = StarkVRF::new(public_key).unwrap();
-    let proof = ecvrf
-        .prove(&secret_key.parse().unwrap(), seed.as_slice())
-        .unwrap();
-    let sqrt_ratio_hint = ecvrf.hash_to_sqrt_ratio_hint(seed.as_slice());
-    let rnd = ecvrf.proof_to_hash(&proof).unwrap();
-
-    debug!("proof gamma: {}", proof.0);
-    debug!("proof c: {}", proof.1);
-    debug!("proof s: {}", proof.2);
-    debug!("proof verify hint: {}", sqrt_ratio_hint);
-
-    let result = StarkVrfProof {
-        gamma_x: format(proof.0.x),
-        gamma_y: format(proof.0.y),
-        c: format(proof.1),
-        s: format(proof.2),
-        sqrt_ratio: format(sqrt_ratio_hint),
-        rnd: format(rnd),
-    };
+    debug!("public key {}", vrf_key.public_key);
+
+    let seed = parse_seed(&payload.seed)?;
+    let result = app_state
+        .prover
+        .run(move || prove(&vrf_key, &seed))
+        .await??;
 
     debug!("result {result:?}");
 
     //let n = (payload.n as f64).sqrt() as u64;
-    Json(JsonResult { result })
+    Ok(Json(JsonResult { result }))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProofBatchRequest {
+    pub seeds: Vec<Vec<String>>,
+}
+
+/// Proof of one seed of a batch, exactly one of `result` and `error` is set.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProofBatchItem {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<StarkVrfProof>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorBody>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProofBatchResult {
+    pub results: Vec<ProofBatchItem>,
+}
+
+// curl -X POST -H "Content-Type: application/json" -d '{"seeds": [["0x1"], ["0x2", "0x3"]]}' http://0.0.0.0:3000/proof/batch
+
+pub async fn vrf_proof_batch(
+    State(state): State<SharedState>,
+    payload: Result<Json<ProofBatchRequest>, JsonRejection>,
+) -> Result<Json<ProofBatchResult>, Errors> {
+    let Json(payload) = payload?;
+    let app_state = state.get().await;
+
+    let max_batch_size = app_state.config.limits.max_proof_batch_size;
+    if payload.seeds.len() > max_batch_size {
+        return Err(Errors::BatchTooLarge(format!(
+            "{} seeds, at most {max_batch_size} are allowed",
+            payload.seeds.len()
+        )));
+    }
+
+    // seeds are proven concurrently on the prover pool, results keep the request order
+    let jobs: Vec<_> = payload
+        .seeds
+        .into_iter()
+        .map(|seed| {
+            let vrf_key = app_state.vrf_key.clone();
+            move || {
+                let seed = parse_seed(&seed)?;
+                prove(&vrf_key, &seed)
+            }
+        })
+        .collect();
+
+    let mut results = vec![];
+    for result in app_state.prover.run_all(jobs).await? {
+        results.push(match result {
+            Ok(proof) => ProofBatchItem {
+                result: Some(proof),
+                error: None,
+            },
+            Err(error) => {
+                metrics::record_error(error.code());
+                ProofBatchItem {
+                    result: None,
+                    error: Some(error.body()),
+                }
+            }
+        });
+    }
+
+    Ok(Json(ProofBatchResult { results }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seed(elements: &[&str]) -> Vec<String> {
+        elements.iter().map(|element| element.to_string()).collect()
+    }
+
+    fn invalid_seed_message(elements: &[&str]) -> String {
+        match parse_seed(&seed(elements)) {
+            Err(Errors::InvalidSeed(msg)) => msg,
+            other => panic!("expected an invalid seed, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_hex_and_decimal_elements() {
+        let expected = parse_seed(&seed(&["0x1a4"])).unwrap();
+
+        assert_eq!(parse_seed(&seed(&["0X1A4"])).unwrap(), expected);
+        assert_eq!(parse_seed(&seed(&["420"])).unwrap(), expected);
+        assert_eq!(parse_seed(&seed(&["0x1", "2", "0xff"])).unwrap().len(), 3);
+
+        // unprefixed hex, told apart from decimal by its letters
+        assert_eq!(parse_seed(&seed(&["1a4"])).unwrap(), expected);
+        assert_eq!(
+            parse_seed(&seed(&["ff"])).unwrap(),
+            parse_seed(&seed(&["0xff"])).unwrap()
+        );
+
+        // bare digits are decimal, never hex
+        assert_ne!(
+            parse_seed(&seed(&["100"])).unwrap(),
+            parse_seed(&seed(&["0x100"])).unwrap()
+        );
+    }
+
+    #[test]
+    fn parses_largest_field_element() {
+        assert!(parse_seed(&seed(&[
+            "0x800000000000011000000000000000000000000000000000000000000000000"
+        ]))
+        .is_ok());
+    }
+
+    #[test]
+    fn rejects_empty_seed() {
+        assert_eq!(invalid_seed_message(&[]), "seed must not be empty");
+    }
+
+    #[test]
+    fn rejects_empty_element() {
+        assert!(invalid_seed_message(&["0x1", ""]).starts_with("seed[1] ``"));
+        assert!(invalid_seed_message(&["0x"]).starts_with("seed[0]"));
+    }
+
+    #[test]
+    fn rejects_non_numeric_element() {
+        assert!(invalid_seed_message(&["0x1", "0x2", "0xzz"]).starts_with("seed[2] `0xzz`"));
+        assert!(invalid_seed_message(&["hello"]).starts_with("seed[0] `hello`"));
+        assert!(invalid_seed_message(&["-1"]).starts_with("seed[0] `-1`"));
+        assert!(invalid_seed_message(&["0x1", "1g"]).starts_with("seed[1] `1g`"));
+    }
+
+    #[test]
+    fn rejects_element_above_field_prime() {
+        let message = invalid_seed_message(&[&format!("0x{FIELD_PRIME}")]);
+        assert!(message.ends_with("must be lower than the Stark field prime"));
+
+        // decimal representation of the field prime
+        let message = invalid_seed_message(&[
+            "3618502788666131213697322783095070105623107215331596699973092056135872020481",
+        ]);
+        assert!(message.ends_with("must be lower than the Stark field prime"));
+    }
 }
@@ -0,0 +1,366 @@
+// Readiness of the instance, meant for load balancer health checks.
+//
+// Every configured chain is checked concurrently: RPC reachability, VRF account
+// deployment, on-chain VRF public key and fee token balance. Only the balance
+// check is not critical, it is reported without draining the instance. The public
+// key is read with `get_vrf_public_key`, or `get_public_key` on legacy providers.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use num::BigUint;
+use serde::{Deserialize, Serialize};
+use starknet::core::types::{BlockId, BlockTag, FunctionCall};
+use starknet::core::utils::parse_cairo_short_string;
+use starknet::macros::selector;
+use starknet::providers::jsonrpc::HttpTransport;
+use starknet::providers::{JsonRpcClient, Provider, ProviderError};
+use starknet_crypto::Felt;
+
+use crate::config::ReadinessConfig;
+use crate::metrics;
+use crate::state::chains::ChainEntry;
+use crate::state::vrf_account::{fetch_vrf_public_key, format_public_key};
+use crate::state::SharedState;
+
+pub const RPC_CHECK: &str = "rpc";
+pub const VRF_ACCOUNT_CHECK: &str = "vrf_account";
+pub const VRF_PUBLIC_KEY_CHECK: &str = "vrf_public_key";
+pub const FEE_TOKEN_BALANCE_CHECK: &str = "fee_token_balance";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    Failed,
+    Skipped,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CheckReport {
+    pub name: String,
+    pub status: CheckStatus,
+    /// A failed critical check makes the instance not ready.
+    pub critical: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl CheckReport {
+    fn new(name: &str, status: CheckStatus, critical: bool, message: Option<String>) -> Self {
+        CheckReport {
+            name: name.to_string(),
+            status,
+            critical,
+            message,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChainReport {
+    pub name: String,
+    pub chain_id: String,
+    pub ready: bool,
+    pub checks: Vec<CheckReport>,
+}
+
+impl ChainReport {
+    fn new(chain: &ChainEntry, checks: Vec<CheckReport>) -> Self {
+        let ready = !checks
+            .iter()
+            .any(|check| check.critical && check.status == CheckStatus::Failed);
+
+        ChainReport {
+            name: chain.name.clone(),
+            chain_id: parse_cairo_short_string(&chain.chain_id)
+                .unwrap_or_else(|_| format!("{:#x}", chain.chain_id)),
+            ready,
+            checks,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadyResult {
+    pub ready: bool,
+    pub chains: Vec<ChainReport>,
+}
+
+/// Runs the readiness checks of `chain`, checks depending on a failed one are skipped.
+pub async fn check_chain(chain: &ChainEntry, config: &ReadinessConfig) -> ChainReport {
+    let checks = [
+        (RPC_CHECK, true),
+        (VRF_ACCOUNT_CHECK, true),
+        (VRF_PUBLIC_KEY_CHECK, true),
+        (FEE_TOKEN_BALANCE_CHECK, false),
+    ];
+    let skip_from = |reports: &mut Vec<CheckReport>, message: &str| {
+        for (name, critical) in &checks[reports.len()..] {
+            reports.push(CheckReport::new(
+                name,
+                CheckStatus::Skipped,
+                *critical,
+                Some(message.to_string()),
+            ));
+        }
+    };
+
+    let mut reports = vec![];
+
+    let Some(rpc_url) = chain.rpc_url.clone() else {
+        skip_from(&mut reports, "no rpc_url configured");
+        return ChainReport::new(chain, reports);
+    };
+    let provider = JsonRpcClient::new(HttpTransport::new(rpc_url));
+    let timeout = Duration::from_secs(config.timeout);
+    let vrf_account_address = chain.vrf_account_address.0;
+
+    let rpc = match rpc_call(chain.chain_id, "chain_id", timeout, provider.chain_id()).await {
+        Ok(chain_id) if chain_id == chain.chain_id => Ok(()),
+        Ok(chain_id) => Err(format!("rpc serves chain {chain_id:#x}")),
+        Err(error) => Err(error),
+    };
+    reports.push(report(RPC_CHECK, true, rpc.map(|_| None)));
+    if reports[0].status == CheckStatus::Failed {
+        skip_from(&mut reports, "rpc check failed");
+        return ChainReport::new(chain, reports);
+    }
+
+    let class_hash = rpc_call(
+        chain.chain_id,
+        "get_class_hash_at",
+        timeout,
+        provider.get_class_hash_at(BlockId::Tag(BlockTag::PreConfirmed), vrf_account_address),
+    )
+    .await;
+    reports.push(report(
+        VRF_ACCOUNT_CHECK,
+        true,
+        class_hash.map(|class_hash| Some(format!("class hash {class_hash:#x}"))),
+    ));
+    if reports[1].status == CheckStatus::Failed {
+        skip_from(&mut reports, "vrf account is not deployed");
+        return ChainReport::new(chain, reports);
+    }
+
+    let expected = chain.vrf_key.public_key_felts();
+    let public_key = rpc_call(
+        chain.chain_id,
+        "get_vrf_public_key",
+        timeout,
+        fetch_vrf_public_key(&provider, vrf_account_address),
+    )
+    .await
+    .and_then(|public_key| {
+        if public_key == expected {
+            Ok(None)
+        } else {
+            Err(format!(
+                "on-chain public key {} does not match {}",
+                format_public_key(&public_key),
+                format_public_key(&expected)
+            ))
+        }
+    });
+    reports.push(report(VRF_PUBLIC_KEY_CHECK, true, public_key));
+
+    let balance = call(
+        &provider,
+        chain.chain_id,
+        timeout,
+        config.fee_token,
+        selector!("balance_of"),
+        vec![vrf_account_address],
+    )
+    .await
+    .and_then(|balance| match balance.as_slice() {
+        [low, high] => Ok(low.to_biguint() + (high.to_biguint() << 128)),
+        _ => Err(format!("unexpected balance_of result {balance:?}")),
+    })
+    .and_then(|balance| {
+        if balance >= BigUint::from(config.min_fee_token_balance) {
+            Ok(Some(format!("balance {balance}")))
+        } else {
+            Err(format!(
+                "balance {balance} is below {}",
+                config.min_fee_token_balance
+            ))
+        }
+    });
+    reports.push(report(FEE_TOKEN_BALANCE_CHECK, false, balance));
+
+    ChainReport::new(chain, reports)
+}
+
+fn report(name: &str, critical: bool, result: Result<Option<String>, String>) -> CheckReport {
+    match result {
+        Ok(message) => CheckReport::new(name, CheckStatus::Ok, critical, message),
+        Err(message) => CheckReport::new(name, CheckStatus::Failed, critical, Some(message)),
+    }
+}
+
+async fn call(
+    provider: &JsonRpcClient<HttpTransport>,
+    chain_id: Felt,
+    timeout: Duration,
+    contract_address: Felt,
+    entry_point_selector: Felt,
+    calldata: Vec<Felt>,
+) -> Result<Vec<Felt>, String> {
+    rpc_call(
+        chain_id,
+        "call",
+        timeout,
+        provider.call(
+            FunctionCall {
+                contract_address,
+                entry_point_selector,
+                calldata,
+            },
+            BlockId::Tag(BlockTag::PreConfirmed),
+        ),
+    )
+    .await
+}
+
+async fn rpc_call<T>(
+    chain_id: Felt,
+    method: &'static str,
+    timeout: Duration,
+    request: impl Future<Output = Result<T, ProviderError>>,
+) -> Result<T, String> {
+    let start = Instant::now();
+    let result = tokio::time::timeout(timeout, request).await;
+
+    let failed = match &result {
+        Ok(Ok(_)) => false,
+        Ok(Err(error)) => metrics::is_rpc_failure(error),
+        Err(_) => true,
+    };
+    metrics::record_rpc(chain_id, method, start, !failed);
+
+    match result {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(_) => Err(format!("timed out after {}s", timeout.as_secs())),
+    }
+}
+
+// curl http://0.0.0.0:3000/ready
+
+pub async fn vrf_ready(State(state): State<SharedState>) -> (StatusCode, Json<ReadyResult>) {
+    let app_state = state.get().await;
+
+    let handles: Vec<_> = app_state
+        .chains
+        .iter()
+        .map(|chain| {
+            let chain = chain.clone();
+            let config = app_state.config.readiness.clone();
+            tokio::spawn(async move { check_chain(&chain, &config).await })
+        })
+        .collect();
+
+    let mut chains = vec![];
+    for (handle, chain) in handles.into_iter().zip(app_state.chains.iter()) {
+        chains.push(match handle.await {
+            Ok(report) => report,
+            Err(error) => ChainReport::new(
+                chain,
+                vec![CheckReport::new(
+                    RPC_CHECK,
+                    CheckStatus::Failed,
+                    true,
+                    Some(format!("check failed: {error}")),
+                )],
+            ),
+        });
+    }
+
+    let ready = chains.iter().all(|chain| chain.ready);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(ReadyResult { ready, chains }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::chains::ChainRegistry;
+    use crate::vrf::VrfKey;
+    use starknet::core::chain_id::MAINNET;
+    use starknet::macros::felt;
+    use url::Url;
+
+    fn chain(rpc_url: Option<&str>) -> ChainEntry {
+        let mut config = Config::default();
+        config.account.address = Some(felt!("0x123"));
+        config.account.private_key.value = Some("0x420".into());
+
+        let mut entry = crate::config::ChainConfig::new(MAINNET);
+        entry.rpc_url = rpc_url.map(|url| Url::parse(url).unwrap());
+        config.chains.insert("mainnet".into(), entry);
+
+        let registry =
+            ChainRegistry::from_config(&config, &VrfKey::from_secret_key(felt!("0x1a4"))).unwrap();
+        registry.get(MAINNET).unwrap().clone()
+    }
+
+    fn statuses(report: &ChainReport) -> Vec<CheckStatus> {
+        report.checks.iter().map(|check| check.status).collect()
+    }
+
+    #[tokio::test]
+    async fn skips_checks_without_rpc_url() {
+        let report = check_chain(&chain(None), &ReadinessConfig::default()).await;
+
+        assert!(report.ready);
+        assert_eq!(report.chain_id, "SN_MAIN");
+        assert_eq!(statuses(&report), vec![CheckStatus::Skipped; 4]);
+    }
+
+    #[tokio::test]
+    async fn unreachable_rpc_is_not_ready() {
+        let config = ReadinessConfig {
+            timeout: 1,
+            ..Default::default()
+        };
+        let report = check_chain(&chain(Some("http://127.0.0.1:1")), &config).await;
+
+        assert!(!report.ready);
+        assert_eq!(
+            statuses(&report),
+            vec![
+                CheckStatus::Failed,
+                CheckStatus::Skipped,
+                CheckStatus::Skipped,
+                CheckStatus::Skipped
+            ]
+        );
+    }
+
+    #[test]
+    fn non_critical_failures_keep_chain_ready() {
+        let checks = vec![
+            CheckReport::new(RPC_CHECK, CheckStatus::Ok, true, None),
+            CheckReport::new(FEE_TOKEN_BALANCE_CHECK, CheckStatus::Failed, false, None),
+        ];
+        assert!(ChainReport::new(&chain(None), checks).ready);
+
+        let checks = vec![CheckReport::new(
+            VRF_PUBLIC_KEY_CHECK,
+            CheckStatus::Failed,
+            true,
+            None,
+        )];
+        assert!(!ChainReport::new(&chain(None), checks).ready);
+    }
+}
@@ -0,0 +1,32 @@
+use axum::extract::{Path, State};
+use axum::Json;
+use chrono::Utc;
+use starknet::core::types::Felt;
+
+use crate::routes::errors::Errors;
+use crate::state::tracker::TrackedBundle;
+use crate::state::SharedState;
+
+// curl http://0.0.0.0:3000/status/0x...
+
+/// Status of a bundle handed out by the server, `id` is its outer nonce or, for
+/// relayed bundles, its transaction hash. Ids that are not a felt are rejected,
+/// well-formed ids that are not tracked are not found.
+pub async fn vrf_status(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+) -> Result<Json<TrackedBundle>, Errors> {
+    let app_state = state.get().await;
+
+    let Ok(bundle_id) = Felt::from_hex(&id) else {
+        return Err(Errors::InvalidBundleId(id));
+    };
+
+    let bundle = app_state
+        .tracker
+        .refresh(bundle_id, Utc::now().timestamp() as u64)
+        .await
+        .ok_or(Errors::BundleNotFound(id))?;
+
+    Ok(Json(bundle))
+}
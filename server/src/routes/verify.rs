@@ -0,0 +1,258 @@
+use crate::oracle::StarkVrfProof;
+use crate::routes::errors::Errors;
+use crate::routes::proof::parse_seed;
+use crate::state::SharedState;
+use crate::vrf::{base_field_to_felt, felt_to_base_field, felt_to_scalar, ScalarField};
+use ark_ec::short_weierstrass::Affine;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::State;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use stark_vrf::{BaseField, Proof, StarkCurve, StarkVRF};
+use starknet::core::types::Felt;
+use tracing::debug;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyRequest {
+    pub proof: StarkVrfProof,
+    pub seed: Vec<String>,
+    /// Public key the proof was made for, defaults to the server's key.
+    #[serde(default)]
+    pub public_key_x: Option<String>,
+    #[serde(default)]
+    pub public_key_y: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyResult {
+    pub rnd: String,
+}
+
+/// Verifies `proof` for `seed` with the `StarkVRF` of the public key it was made for,
+/// and returns the derived `rnd`.
+///
+/// `sqrt_ratio` must be the hint of `seed`, and `rnd` must match when it is set.
+pub fn verify(
+    proof: &StarkVrfProof,
+    seed: &[BaseField],
+    ecvrf: &StarkVRF,
+) -> Result<BaseField, Errors> {
+    let gamma = Affine::<StarkCurve>::new_unchecked(
+        parse_base_field("gamma_x", &proof.gamma_x)?,
+        parse_base_field("gamma_y", &proof.gamma_y)?,
+    );
+    if !gamma.is_on_curve() || !gamma.is_in_correct_subgroup_assuming_on_curve() {
+        return Err(Errors::InvalidProof(
+            "gamma is not a curve point".to_string(),
+        ));
+    }
+
+    let c = parse_scalar_field("c", &proof.c)?;
+    let s = parse_scalar_field("s", &proof.s)?;
+
+    if ecvrf.hash_to_sqrt_ratio_hint(seed) != parse_base_field("sqrt_ratio", &proof.sqrt_ratio)? {
+        return Err(Errors::InvalidProof(
+            "sqrt_ratio does not match the seed".to_string(),
+        ));
+    }
+
+    let vrf_proof = Proof(gamma, c, s);
+    ecvrf
+        .verify(&vrf_proof, seed)
+        .map_err(|e| Errors::InvalidProof(format!("verification failed: {e:?}")))?;
+
+    let rnd = ecvrf
+        .proof_to_hash(&vrf_proof)
+        .map_err(|e| Errors::InvalidProof(format!("failed to hash proof: {e:?}")))?;
+
+    if !proof.rnd.is_empty() && rnd != parse_base_field("rnd", &proof.rnd)? {
+        return Err(Errors::InvalidProof(
+            "rnd does not match the proof".to_string(),
+        ));
+    }
+
+    Ok(rnd)
+}
+
+/// Parses a `0x` prefixed felt, values at or above the Stark prime are rejected.
+///
+/// Fields that don't parse are malformed (`400`), unlike proofs that parse but fail
+/// verification (`422`).
+fn parse_felt(name: &str, value: &str) -> Result<Felt, Errors> {
+    if !value.starts_with("0x") && !value.starts_with("0X") {
+        return Err(Errors::MalformedProof(format!(
+            "{name} must be a 0x prefixed hex number"
+        )));
+    }
+
+    Felt::from_hex(value)
+        .map_err(|_| Errors::MalformedProof(format!("{name} is not a field element")))
+}
+
+fn parse_base_field(name: &str, value: &str) -> Result<BaseField, Errors> {
+    parse_felt(name, value).map(felt_to_base_field)
+}
+
+fn parse_scalar_field(name: &str, value: &str) -> Result<ScalarField, Errors> {
+    felt_to_scalar(parse_felt(name, value)?)
+        .ok_or_else(|| Errors::MalformedProof(format!("{name} is not a scalar")))
+}
+
+// curl -X POST -H "Content-Type: application/json" -d '{"seed": ["0x1"], "proof": {"gamma_x": "0x...", "gamma_y": "0x...", "c": "0x...", "s": "0x...", "sqrt_ratio": "0x...", "rnd": "0x..."}}' http://0.0.0.0:3000/verify
+
+pub async fn vrf_verify(
+    State(state): State<SharedState>,
+    payload: Result<Json<VerifyRequest>, JsonRejection>,
+) -> Result<Json<VerifyResult>, Errors> {
+    let Json(payload) = payload?;
+    debug!("received payload {payload:?}");
+
+    let app_state = state.get().await;
+
+    // the server's key is prepared once, other keys for this request only
+    let public_key = match (&payload.public_key_x, &payload.public_key_y) {
+        (None, None) => None,
+        (Some(x), Some(y)) => {
+            let public_key = Affine::<StarkCurve>::new_unchecked(
+                parse_base_field("public_key_x", x)?,
+                parse_base_field("public_key_y", y)?,
+            );
+            if !public_key.is_on_curve() {
+                return Err(Errors::MalformedProof(
+                    "public key is not a curve point".to_string(),
+                ));
+            }
+            Some(public_key)
+        }
+        _ => {
+            return Err(Errors::MalformedProof(
+                "public_key_x and public_key_y must be set together".to_string(),
+            ))
+        }
+    };
+
+    let seed = parse_seed(&payload.seed)?;
+    let proof = payload.proof;
+    let vrf_key = app_state.vrf_key;
+    let rnd = app_state
+        .prover
+        .run(move || match public_key {
+            None => verify(&proof, &seed, vrf_key.ecvrf()),
+            Some(public_key) => {
+                let ecvrf = StarkVRF::new(public_key)
+                    .map_err(|e| Errors::MalformedProof(format!("invalid public key: {e:?}")))?;
+                verify(&proof, &seed, &ecvrf)
+            }
+        })
+        .await??;
+
+    Ok(Json(VerifyResult {
+        rnd: base_field_to_felt(rnd).to_hex_string(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::proof::prove;
+    use crate::vrf::VrfKey;
+    use starknet::macros::felt;
+
+    fn prove_seed(seed: &[BaseField]) -> (VrfKey, StarkVrfProof) {
+        let vrf_key = VrfKey::from_secret_key(felt!("0x1a4"));
+        let proof = prove(&vrf_key, seed).unwrap();
+        (vrf_key, proof)
+    }
+
+    fn seed(elements: &[&str]) -> Vec<BaseField> {
+        parse_seed(&elements.iter().map(|e| e.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn verifies_proof_and_returns_rnd() {
+        let seed = seed(&["0x1", "0x2"]);
+        let (vrf_key, proof) = prove_seed(&seed);
+
+        let rnd = verify(&proof, &seed, vrf_key.ecvrf()).unwrap();
+        assert_eq!(base_field_to_felt(rnd).to_hex_string(), proof.rnd);
+
+        let without_rnd = StarkVrfProof {
+            rnd: String::new(),
+            ..proof
+        };
+        assert!(verify(&without_rnd, &seed, vrf_key.ecvrf()).is_ok());
+    }
+
+    #[test]
+    fn rejects_other_seed_or_key() {
+        let (vrf_key, proof) = prove_seed(&seed(&["0x1"]));
+
+        assert!(matches!(
+            verify(&proof, &seed(&["0x2"]), vrf_key.ecvrf()),
+            Err(Errors::InvalidProof(_))
+        ));
+
+        let other_key = VrfKey::from_secret_key(felt!("0x1a5"));
+        assert!(matches!(
+            verify(&proof, &seed(&["0x1"]), other_key.ecvrf()),
+            Err(Errors::InvalidProof(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_tampered_proof() {
+        let seed = seed(&["0x1"]);
+        let (vrf_key, proof) = prove_seed(&seed);
+
+        let tampered = StarkVrfProof {
+            s: "0x1".into(),
+            ..proof.clone()
+        };
+        assert!(matches!(
+            verify(&tampered, &seed, vrf_key.ecvrf()),
+            Err(Errors::InvalidProof(_))
+        ));
+
+        let off_curve = StarkVrfProof {
+            gamma_y: "0x1".into(),
+            ..proof.clone()
+        };
+        assert!(matches!(
+            verify(&off_curve, &seed, vrf_key.ecvrf()),
+            Err(Errors::InvalidProof(_))
+        ));
+
+        let wrong_rnd = StarkVrfProof {
+            rnd: "0x1".into(),
+            ..proof
+        };
+        assert!(matches!(
+            verify(&wrong_rnd, &seed, vrf_key.ecvrf()),
+            Err(Errors::InvalidProof(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_fields() {
+        let seed = seed(&["0x1"]);
+        let (vrf_key, proof) = prove_seed(&seed);
+
+        let unprefixed = StarkVrfProof {
+            c: proof.c.trim_start_matches("0x").into(),
+            ..proof.clone()
+        };
+        assert!(matches!(
+            verify(&unprefixed, &seed, vrf_key.ecvrf()),
+            Err(Errors::MalformedProof(_))
+        ));
+
+        let not_hex = StarkVrfProof {
+            gamma_x: "0xzz".into(),
+            ..proof
+        };
+        assert!(matches!(
+            verify(&not_hex, &seed, vrf_key.ecvrf()),
+            Err(Errors::MalformedProof(_))
+        ));
+    }
+}
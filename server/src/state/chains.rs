@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Context};
+use cainome_cairo_serde::ContractAddress;
+use starknet::core::types::Felt;
+use starknet::signers::{LocalWallet, SigningKey};
+use url::Url;
+
+use crate::config::Config;
+use crate::state::relayers::{Relayer, RelayerPool};
+use crate::state::secrets::{parse_account_private_key, parse_vrf_secret_key, SecretOptions};
+use crate::vrf::VrfKey;
+
+/// Loads the VRF key of `options`, `None` when no source is set.
+pub fn load_vrf_key(options: &SecretOptions, name: &str) -> anyhow::Result<Option<VrfKey>> {
+    let Some(source) = options.source(name)? else {
+        return Ok(None);
+    };
+
+    let secret_key = source
+        .load(parse_vrf_secret_key)
+        .with_context(|| format!("invalid VRF secret key ({name})"))?;
+
+    Ok(Some(VrfKey::from_secret_key(secret_key)))
+}
+
+/// VRF account, key and RPC used to serve a chain.
+#[derive(Clone)]
+pub struct ChainEntry {
+    pub name: String,
+    pub chain_id: Felt,
+    pub rpc_url: Option<Url>,
+    pub allowed_rpc_urls: Vec<String>,
+    pub allowed_executors: Vec<Felt>,
+    pub relayers: RelayerPool,
+    pub vrf_account_address: ContractAddress,
+    pub vrf_signer: LocalWallet,
+    pub vrf_key: VrfKey,
+}
+
+/// Chains served by this instance, keyed by chain id.
+#[derive(Clone, Default)]
+pub struct ChainRegistry {
+    chains: HashMap<Felt, ChainEntry>,
+}
+
+impl ChainRegistry {
+    /// Builds the registry from `config.chains`, chain entries fall back to the
+    /// top level `account` and `vrf` settings when they don't override them.
+    pub fn from_config(config: &Config, default_vrf_key: &VrfKey) -> anyhow::Result<Self> {
+        let default_signer = load_signer(&config.account.private_key, "account-private-key")?;
+
+        let mut chains = HashMap::new();
+
+        for (name, chain) in &config.chains {
+            let vrf_account_address = chain
+                .vrf_account_address
+                .or(config.account.address)
+                .map(ContractAddress::from)
+                .ok_or_else(|| {
+                    anyhow!("missing VRF account address for chain `{name}`, set chains.{name}.vrf_account_address or account.address")
+                })?;
+
+            let vrf_signer = match load_signer(&chain.private_key, &format!("chains.{name}.private_key"))? {
+                Some(signer) => signer,
+                None => default_signer.clone().ok_or_else(|| {
+                    anyhow!("missing account private key for chain `{name}`, set [chains.{name}.private_key] or the account private key")
+                })?,
+            };
+
+            let vrf_key = load_vrf_key(
+                &chain.vrf_secret_key,
+                &format!("chains.{name}.vrf_secret_key"),
+            )?
+            .unwrap_or_else(|| default_vrf_key.clone());
+
+            let mut relayers = vec![];
+            for (index, relayer) in chain.relayers.iter().enumerate() {
+                let signer = load_signer(
+                    &relayer.private_key,
+                    &format!("chains.{name}.relayers[{index}].private_key"),
+                )?
+                .ok_or_else(|| {
+                    anyhow!(
+                        "missing private key for relayer {:#x} of chain `{name}`",
+                        relayer.address
+                    )
+                })?;
+                relayers.push(Relayer::new(relayer.address, signer, chain.relayer_max_fee));
+            }
+
+            let entry = ChainEntry {
+                name: name.clone(),
+                chain_id: chain.chain_id,
+                rpc_url: chain.rpc_url.clone(),
+                allowed_rpc_urls: chain.allowed_rpc_urls.clone(),
+                allowed_executors: chain.allowed_executors.clone(),
+                relayers: RelayerPool::new(relayers),
+                vrf_account_address,
+                vrf_signer,
+                vrf_key,
+            };
+
+            if chains.insert(chain.chain_id, entry).is_some() {
+                bail!(
+                    "chain id {:#x} is configured more than once",
+                    chain.chain_id
+                );
+            }
+        }
+
+        Ok(ChainRegistry { chains })
+    }
+
+    pub fn get(&self, chain_id: Felt) -> Option<&ChainEntry> {
+        self.chains.get(&chain_id)
+    }
+
+    /// Chains ordered by name.
+    pub fn iter(&self) -> impl Iterator<Item = &ChainEntry> {
+        let mut chains: Vec<_> = self.chains.values().collect();
+        chains.sort_by(|a, b| a.name.cmp(&b.name));
+        chains.into_iter()
+    }
+}
+
+fn load_signer(options: &SecretOptions, name: &str) -> anyhow::Result<Option<LocalWallet>> {
+    let Some(source) = options.source(name)? else {
+        return Ok(None);
+    };
+
+    let private_key = source
+        .load(parse_account_private_key)
+        .with_context(|| format!("invalid account private key ({name})"))?;
+
+    Ok(Some(LocalWallet::from(SigningKey::from_secret_scalar(
+        private_key,
+    ))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vrf::ScalarField;
+    use starknet::core::chain_id::{MAINNET, SEPOLIA};
+    use starknet::core::utils::cairo_short_string_to_felt;
+    use starknet::macros::felt;
+
+    fn default_vrf_key() -> VrfKey {
+        VrfKey::from_secret_key(felt!("0x1a4"))
+    }
+
+    #[test]
+    fn chains_fall_back_to_default_account() {
+        let config = Config::from_toml(
+            r#"
+            [account]
+            address = "0x123"
+
+            [account.private_key]
+            value = "0x111"
+
+            [chains.mainnet]
+            chain_id = "SN_MAIN"
+
+            [chains.katana]
+            chain_id = "WP_KATANA"
+            vrf_account_address = "0x456"
+
+            [chains.katana.private_key]
+            value = "0x222"
+
+            [chains.katana.vrf_secret_key]
+            value = "0x1a5"
+            "#,
+        )
+        .unwrap();
+
+        let registry = ChainRegistry::from_config(&config, &default_vrf_key()).unwrap();
+
+        let mainnet = registry.get(MAINNET).unwrap();
+        assert_eq!(mainnet.vrf_account_address, ContractAddress(felt!("0x123")));
+        assert_eq!(mainnet.vrf_key.secret_key, ScalarField::from(420u64));
+
+        let katana = registry
+            .get(cairo_short_string_to_felt("WP_KATANA").unwrap())
+            .unwrap();
+        assert_eq!(katana.vrf_account_address, ContractAddress(felt!("0x456")));
+        assert_eq!(katana.vrf_key.secret_key, ScalarField::from(421u64));
+
+        assert!(registry.get(SEPOLIA).is_none());
+    }
+
+    #[test]
+    fn rejects_chain_without_signer() {
+        let config = Config::from_toml(
+            r#"
+            [chains.mainnet]
+            chain_id = "SN_MAIN"
+            vrf_account_address = "0x123"
+            "#,
+        )
+        .unwrap();
+
+        assert!(ChainRegistry::from_config(&config, &default_vrf_key()).is_err());
+    }
+
+    #[test]
+    fn rejects_duplicated_chain_id() {
+        let config = Config::from_toml(
+            r#"
+            [account]
+            address = "0x123"
+
+            [account.private_key]
+            value = "0x111"
+
+            [chains.mainnet]
+            chain_id = "SN_MAIN"
+
+            [chains.mainnet_bis]
+            chain_id = "0x534e5f4d41494e"
+            "#,
+        )
+        .unwrap();
+
+        assert!(ChainRegistry::from_config(&config, &default_vrf_key()).is_err());
+    }
+}
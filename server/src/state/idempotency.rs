@@ -0,0 +1,122 @@
+//! Responses of identical requests, kept until the bundle they return expires.
+//!
+//! Retries of an outside execution request get the bundle built for the first
+//! one instead of reserving another nonce and proving again. Concurrent identical
+//! requests wait for the first one, failed builds are not kept.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::OnceCell;
+
+type Entry<T> = Arc<OnceCell<(T, u64)>>;
+
+#[derive(Debug)]
+pub struct IdempotencyCache<K, T> {
+    entries: Arc<Mutex<HashMap<K, Entry<T>>>>,
+}
+
+impl<K, T> Clone for IdempotencyCache<K, T> {
+    fn clone(&self) -> Self {
+        IdempotencyCache {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<K, T> Default for IdempotencyCache<K, T> {
+    fn default() -> Self {
+        IdempotencyCache {
+            entries: Arc::default(),
+        }
+    }
+}
+
+impl<K: Hash + Eq, T: Clone> IdempotencyCache<K, T> {
+    /// Returns the value kept for `key`, or the one returned by `build` with the
+    /// timestamp it expires at.
+    pub async fn get_or_try_insert<F, Fut, E>(&self, key: K, now: u64, build: F) -> Result<T, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<(T, u64), E>>,
+    {
+        let entry = {
+            let mut entries = self.entries.lock().unwrap();
+            entries.retain(|_, entry| match entry.get() {
+                Some((_, expires_at)) => *expires_at > now,
+                // failed builds, nobody is building or waiting for them anymore
+                None => Arc::strong_count(entry) > 1,
+            });
+            entries.entry(key).or_default().clone()
+        };
+
+        let (value, _) = entry.get_or_try_init(build).await?;
+        Ok(value.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn keeps_values_until_they_expire() {
+        let cache = IdempotencyCache::<u64, u64>::default();
+        let counter = AtomicUsize::new(0);
+        let builds = &counter;
+        let build = |value| {
+            move || async move {
+                builds.fetch_add(1, Ordering::SeqCst);
+                Ok::<_, ()>((value, 200))
+            }
+        };
+
+        assert_eq!(cache.get_or_try_insert(1, 100, build(10)).await, Ok(10));
+        assert_eq!(cache.get_or_try_insert(1, 150, build(11)).await, Ok(10));
+        assert_eq!(cache.get_or_try_insert(2, 150, build(20)).await, Ok(20));
+        assert_eq!(builds.load(Ordering::SeqCst), 2);
+
+        // expired
+        assert_eq!(cache.get_or_try_insert(1, 200, build(12)).await, Ok(12));
+        assert_eq!(builds.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn failed_builds_are_not_kept() {
+        let cache = IdempotencyCache::<u64, u64>::default();
+
+        let result = cache
+            .get_or_try_insert(1, 100, || async { Err::<(u64, u64), _>("failed") })
+            .await;
+        assert_eq!(result, Err("failed"));
+
+        let result = cache
+            .get_or_try_insert(1, 100, || async { Ok::<_, &str>((10, 200)) })
+            .await;
+        assert_eq!(result, Ok(10));
+        assert_eq!(cache.entries.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_build_once() {
+        let cache = IdempotencyCache::<u64, u64>::default();
+        let counter = AtomicUsize::new(0);
+        let builds = &counter;
+        let build = move || async move {
+            builds.fetch_add(1, Ordering::SeqCst);
+            tokio::task::yield_now().await;
+            Ok::<_, ()>((10, 200))
+        };
+
+        let (first, second) = tokio::join!(
+            cache.get_or_try_insert(1, 100, build),
+            cache.get_or_try_insert(1, 100, build),
+        );
+
+        assert_eq!((first, second), (Ok(10), Ok(10)));
+        assert_eq!(builds.load(Ordering::SeqCst), 1);
+    }
+}
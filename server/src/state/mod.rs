@@ -1,13 +1,26 @@
-use crate::Args;
-use ark_ec::short_weierstrass::Affine;
-use cainome_cairo_serde::ContractAddress;
-use clap::Parser;
-use stark_vrf::{generate_public_key, StarkCurve};
+use crate::config::Config;
+use crate::prover::ProverPool;
+use crate::routes::outside_execution::relay::RelayResult;
+use crate::routes::outside_execution::OutsideExecutionResult;
+use anyhow::anyhow;
 use starknet::core::types::Felt;
-use starknet::signers::{LocalWallet, SigningKey};
 use std::ops::Deref;
 use std::sync::{Arc, RwLock};
 
+pub mod chains;
+pub mod idempotency;
+pub mod nonces;
+pub mod relayers;
+pub mod secrets;
+pub mod tracker;
+pub mod vrf_account;
+
+use crate::vrf::VrfKey;
+use chains::{load_vrf_key, ChainRegistry};
+use idempotency::IdempotencyCache;
+use nonces::NonceReservations;
+use tracker::BundleTracker;
+
 #[derive(Clone)]
 pub struct SharedState(pub Arc<RwLock<AppState>>);
 
@@ -27,33 +40,42 @@ impl SharedState {
 
 #[derive(Clone)]
 pub struct AppState {
-    pub secret_key: String,
-    pub public_key: Affine<StarkCurve>,
-    pub vrf_account_address: ContractAddress,
-    pub vrf_signer: LocalWallet,
+    pub vrf_key: VrfKey,
+    pub chains: ChainRegistry,
+    pub prover: ProverPool,
+    /// `Source::Nonce` nonces of the bundles not confirmed yet, shared by every request.
+    pub nonces: NonceReservations,
+    /// Bundles returned to retried requests, keyed by outer nonce and caller.
+    pub bundles: IdempotencyCache<(Felt, Felt), OutsideExecutionResult>,
+    /// Transactions submitted in relayer mode, keyed by outer nonce.
+    pub relays: IdempotencyCache<Felt, RelayResult>,
+    /// Bundles handed out, served on `/status/{id}`.
+    pub tracker: BundleTracker,
+    pub config: Arc<Config>,
 }
 
 impl AppState {
-    pub async fn new() -> AppState {
-        let args = Args::parse();
-        AppState::from_args(&args).await
-    }
+    pub async fn from_config(config: Config) -> anyhow::Result<AppState> {
+        let vrf_key = load_vrf_key(&config.vrf.secret_key, "secret-key")?.ok_or_else(|| {
+            anyhow!("missing VRF secret key, set one of --secret-key, --secret-key-file, --secret-key-keystore or [vrf.secret_key] in the config file")
+        })?;
+
+        let chains = ChainRegistry::from_config(&config, &vrf_key)?;
+        let nonces = NonceReservations::default();
 
-    pub async fn from_args(args: &Args) -> AppState {
-        let secret_key = args.secret_key.to_string();
-        let public_key = generate_public_key(secret_key.parse().unwrap());
-
-        let vrf_account_address =
-            ContractAddress::from(Felt::from_hex(&args.account_address).unwrap());
-        let vrf_signer = LocalWallet::from(SigningKey::from_secret_scalar(
-            Felt::from_hex(&args.account_private_key).unwrap(),
-        ));
-
-        AppState {
-            secret_key,
-            public_key,
-            vrf_account_address,
-            vrf_signer,
-        }
+        Ok(AppState {
+            vrf_key,
+            chains,
+            prover: ProverPool::new(&config.prover),
+            nonces: nonces.clone(),
+            bundles: IdempotencyCache::default(),
+            relays: IdempotencyCache::default(),
+            tracker: BundleTracker::new(
+                nonces,
+                config.tracking.retention,
+                config.tracking.max_bundles,
+            ),
+            config: Arc::new(config),
+        })
     }
 }
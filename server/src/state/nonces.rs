@@ -0,0 +1,312 @@
+//! `Source::Nonce` nonces handed out to bundles that are not confirmed yet.
+//!
+//! The seed of a `Source::Nonce` request depends on the address nonce stored by
+//! the VRF account, which is only bumped once the bundle lands. Without tracking,
+//! two bundles requested back to back by the same address get the same nonce and
+//! the second one reverts. Each bundle reserves the nonce it consumes until the
+//! stored nonce passes it (confirmation) or its `execute_before` passes (it can no
+//! longer land), or until the tracker sees its relayed transaction revert.
+//!
+//! A bundle handed out and then abandoned blocks the address until it expires:
+//! later bundles are queued behind its nonce and can't land before it does.
+//!
+//! Reservations belong to an outer nonce. Bundles rebuilt for the same user outside
+//! execution (another caller, relayer mode) reuse its nonce, so they get the same
+//! seeds instead of queueing behind a copy that can never execute alongside them.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use starknet::core::types::Felt;
+
+/// Nonces are tracked per chain, VRF account and address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NonceKey {
+    pub chain_id: Felt,
+    pub vrf_account_address: Felt,
+    pub address: Felt,
+}
+
+impl NonceKey {
+    pub fn new(chain_id: Felt, vrf_account_address: Felt, address: Felt) -> Self {
+        NonceKey {
+            chain_id,
+            vrf_account_address,
+            address,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Reserved {
+    expires_at: u64,
+    /// Outer nonce of the bundles consuming the nonce.
+    outer_nonce: Felt,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NonceReservations {
+    /// Reserved nonces of a key.
+    reservations: Arc<Mutex<HashMap<NonceKey, BTreeMap<Felt, Reserved>>>>,
+}
+
+impl NonceReservations {
+    /// Returns the nonce the next bundle of `key` will see, `stored` is the nonce
+    /// read from the VRF account.
+    pub fn pending_nonce(&self, key: NonceKey, stored: Felt, now: u64) -> Felt {
+        let mut reservations = self.reservations.lock().unwrap();
+        next_nonce(&mut reservations, key, stored, now)
+    }
+
+    /// Reserves the nonce the next bundle of `key` will see until `expires_at`, or
+    /// returns the nonce already reserved for `outer_nonce`.
+    ///
+    /// The reservation is released when the returned guard is dropped, unless it
+    /// is kept with [`NonceReservation::keep`] once the bundle is handed out. Reused
+    /// reservations are extended to `expires_at` and stay owned by the first bundle.
+    pub fn reserve(
+        &self,
+        key: NonceKey,
+        stored: Felt,
+        now: u64,
+        expires_at: u64,
+        outer_nonce: Felt,
+    ) -> NonceReservation {
+        let mut reservations = self.reservations.lock().unwrap();
+        let next = next_nonce(&mut reservations, key, stored, now);
+        let nonces = reservations.entry(key).or_default();
+
+        let reused = nonces
+            .iter_mut()
+            .find(|(_, reserved)| reserved.outer_nonce == outer_nonce);
+        let (nonce, kept) = match reused {
+            Some((nonce, reserved)) => {
+                reserved.expires_at = reserved.expires_at.max(expires_at);
+                (*nonce, true)
+            }
+            None => {
+                nonces.insert(
+                    next,
+                    Reserved {
+                        expires_at,
+                        outer_nonce,
+                    },
+                );
+                (next, false)
+            }
+        };
+
+        NonceReservation {
+            reservations: self.clone(),
+            key,
+            nonce,
+            kept,
+        }
+    }
+
+    /// Releases `nonce` of `key`, once its bundle can no longer consume it.
+    pub fn release(&self, key: NonceKey, nonce: Felt) {
+        let mut reservations = self.reservations.lock().unwrap();
+        if let Some(nonces) = reservations.get_mut(&key) {
+            nonces.remove(&nonce);
+            if nonces.is_empty() {
+                reservations.remove(&key);
+            }
+        }
+    }
+}
+
+/// Drops the confirmed and expired reservations of `key`, then returns the nonce
+/// following the reservations that run without a gap from `stored`.
+///
+/// Reservations above a gap, left by a lower bundle that expired or was released,
+/// are dropped: their bundles can't land before the missing nonce is consumed, and
+/// following them would hand out nonces no bundle can reach.
+fn next_nonce(
+    reservations: &mut HashMap<NonceKey, BTreeMap<Felt, Reserved>>,
+    key: NonceKey,
+    stored: Felt,
+    now: u64,
+) -> Felt {
+    let Some(nonces) = reservations.get_mut(&key) else {
+        return stored;
+    };
+
+    nonces.retain(|nonce, reserved| *nonce >= stored && reserved.expires_at > now);
+
+    let mut next = stored;
+    while nonces.contains_key(&next) {
+        next += Felt::ONE;
+    }
+    nonces.retain(|nonce, _| *nonce < next);
+
+    if nonces.is_empty() {
+        reservations.remove(&key);
+    }
+    next
+}
+
+/// Nonce reserved for a bundle, released on drop unless kept.
+#[derive(Debug)]
+pub struct NonceReservation {
+    reservations: NonceReservations,
+    key: NonceKey,
+    nonce: Felt,
+    kept: bool,
+}
+
+impl NonceReservation {
+    pub fn key(&self) -> NonceKey {
+        self.key
+    }
+
+    pub fn nonce(&self) -> Felt {
+        self.nonce
+    }
+
+    /// Keeps the reservation until it is confirmed or expires.
+    pub fn keep(mut self) {
+        self.kept = true;
+    }
+}
+
+impl Drop for NonceReservation {
+    fn drop(&mut self) {
+        if !self.kept {
+            self.reservations.release(self.key, self.nonce);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use starknet::macros::felt;
+
+    const KEY: NonceKey = NonceKey {
+        chain_id: felt!("0x57505f4b4154414e41"),
+        vrf_account_address: felt!("0x111"),
+        address: felt!("0xa"),
+    };
+
+    #[test]
+    fn concurrent_bundles_get_consecutive_nonces() {
+        let reservations = NonceReservations::default();
+
+        let first = reservations.reserve(KEY, felt!("0x5"), 100, 700, felt!("0xb01"));
+        let second = reservations.reserve(KEY, felt!("0x5"), 100, 700, felt!("0xb02"));
+        assert_eq!(first.nonce(), felt!("0x5"));
+        assert_eq!(second.nonce(), felt!("0x6"));
+        assert_eq!(
+            reservations.pending_nonce(KEY, felt!("0x5"), 100),
+            felt!("0x7")
+        );
+
+        // other addresses are not affected
+        let other = NonceKey {
+            address: felt!("0xb"),
+            ..KEY
+        };
+        assert_eq!(
+            reservations.pending_nonce(other, felt!("0x5"), 100),
+            felt!("0x5")
+        );
+
+        first.keep();
+        second.keep();
+    }
+
+    #[test]
+    fn confirmed_and_expired_reservations_are_dropped() {
+        let reservations = NonceReservations::default();
+        reservations
+            .reserve(KEY, felt!("0x5"), 100, 700, felt!("0xb03"))
+            .keep();
+        reservations
+            .reserve(KEY, felt!("0x5"), 100, 800, felt!("0xb04"))
+            .keep();
+
+        // the first bundle landed
+        assert_eq!(
+            reservations.pending_nonce(KEY, felt!("0x6"), 100),
+            felt!("0x7")
+        );
+
+        // the second one can no longer land
+        assert_eq!(
+            reservations.pending_nonce(KEY, felt!("0x6"), 800),
+            felt!("0x6")
+        );
+    }
+
+    #[test]
+    fn reservations_above_a_gap_are_dropped() {
+        let reservations = NonceReservations::default();
+
+        // the lower bundle was abandoned while the higher one is live
+        let released = reservations.reserve(KEY, felt!("0x5"), 100, 700, felt!("0xb05"));
+        reservations
+            .reserve(KEY, felt!("0x5"), 100, 700, felt!("0xb06"))
+            .keep();
+        drop(released);
+        assert_eq!(
+            reservations.pending_nonce(KEY, felt!("0x5"), 100),
+            felt!("0x5")
+        );
+
+        // the lower bundle expired while the higher one is live
+        reservations
+            .reserve(KEY, felt!("0x5"), 100, 200, felt!("0xb07"))
+            .keep();
+        reservations
+            .reserve(KEY, felt!("0x5"), 100, 700, felt!("0xb08"))
+            .keep();
+        assert_eq!(
+            reservations.pending_nonce(KEY, felt!("0x5"), 200),
+            felt!("0x5")
+        );
+    }
+
+    #[test]
+    fn dropped_reservations_are_released() {
+        let reservations = NonceReservations::default();
+
+        let kept = reservations.reserve(KEY, felt!("0x5"), 100, 700, felt!("0xb09"));
+        drop(reservations.reserve(KEY, felt!("0x5"), 100, 700, felt!("0xb0a")));
+        assert_eq!(
+            reservations.pending_nonce(KEY, felt!("0x5"), 100),
+            felt!("0x6")
+        );
+
+        kept.keep();
+        assert_eq!(
+            reservations.pending_nonce(KEY, felt!("0x5"), 100),
+            felt!("0x6")
+        );
+    }
+
+    #[test]
+    fn rebuilt_bundles_reuse_their_reservation() {
+        let reservations = NonceReservations::default();
+        let outer_nonce = felt!("0xb01");
+
+        let first = reservations.reserve(KEY, felt!("0x5"), 100, 700, outer_nonce);
+        let other = reservations.reserve(KEY, felt!("0x5"), 100, 700, felt!("0xb02"));
+        let rebuilt = reservations.reserve(KEY, felt!("0x5"), 100, 800, outer_nonce);
+        assert_eq!(rebuilt.nonce(), first.nonce());
+        assert_eq!(other.nonce(), felt!("0x6"));
+
+        // the first bundle owns the reservation, extended to the rebuilt one's expiry
+        drop(rebuilt);
+        first.keep();
+        other.keep();
+        assert_eq!(
+            reservations.pending_nonce(KEY, felt!("0x5"), 100),
+            felt!("0x7")
+        );
+        assert_eq!(
+            reservations.pending_nonce(KEY, felt!("0x5"), 750),
+            felt!("0x6")
+        );
+    }
+}
@@ -0,0 +1,267 @@
+//! Funded accounts submitting `execute_from_outside` bundles in relayer mode.
+//!
+//! Requests are spread over the relayers of a chain in turn. Each relayer keeps
+//! the nonce of its next transaction and submits one transaction at a time, so
+//! concurrent requests never reuse a nonce. The nonce is read from the chain again
+//! after a failed submission. Transactions are signed with resource bounds whose
+//! max fee is at most the chain's `relayer_max_fee`, and not submitted when their
+//! estimate alone is above it.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use starknet::accounts::{Account, AccountError, ExecutionEncoding, SingleOwnerAccount};
+use starknet::core::types::{BlockId, BlockTag, FeeEstimate, Felt};
+use starknet::providers::jsonrpc::HttpTransport;
+use starknet::providers::{JsonRpcClient, Provider};
+use starknet::signers::LocalWallet;
+use tokio::sync::Mutex;
+
+use crate::metrics;
+use crate::routes::errors::Errors;
+use crate::routes::outside_execution::types::Call;
+
+#[derive(Clone)]
+pub struct Relayer {
+    pub address: Felt,
+    signer: LocalWallet,
+    /// Highest estimated fee paid for one transaction, in the fee token's base unit.
+    max_fee: u128,
+    /// Nonce of the next transaction, `None` until read from the chain.
+    next_nonce: Arc<Mutex<Option<Felt>>>,
+}
+
+impl Relayer {
+    pub fn new(address: Felt, signer: LocalWallet, max_fee: u128) -> Self {
+        Relayer {
+            address,
+            signer,
+            max_fee,
+            next_nonce: Arc::default(),
+        }
+    }
+
+    /// Submits `call` and returns the transaction hash.
+    pub async fn submit(
+        &self,
+        provider: &JsonRpcClient<HttpTransport>,
+        chain_id: Felt,
+        call: Call,
+    ) -> Result<Felt, Errors> {
+        let mut next_nonce = self.next_nonce.lock().await;
+
+        let nonce = match *next_nonce {
+            Some(nonce) => nonce,
+            None => {
+                let start = Instant::now();
+                let nonce = provider
+                    .get_nonce(BlockId::Tag(BlockTag::PreConfirmed), self.address)
+                    .await;
+                metrics::record_rpc(chain_id, "get_nonce", start, nonce.is_ok());
+                nonce?
+            }
+        };
+
+        let mut account = SingleOwnerAccount::new(
+            provider.clone(),
+            self.signer.clone(),
+            self.address,
+            chain_id,
+            ExecutionEncoding::New,
+        );
+        account.set_block_id(BlockId::Tag(BlockTag::PreConfirmed));
+
+        let execution = account.execute_v3(vec![call.into()]).nonce(nonce);
+
+        let start = Instant::now();
+        let estimate = execution.estimate_fee().await;
+        metrics::record_rpc(
+            chain_id,
+            "estimate_fee",
+            start,
+            !matches!(&estimate, Err(AccountError::Provider(error)) if metrics::is_rpc_failure(error)),
+        );
+        let estimate = match estimate {
+            Ok(estimate) => estimate,
+            Err(error) => {
+                *next_nonce = None;
+                return Err(Errors::RelayFailed(format!(
+                    "relayer {:#x}: {error}",
+                    self.address
+                )));
+            }
+        };
+        let Some(bounds) = ResourceBounds::within(&estimate, self.max_fee) else {
+            return Err(Errors::RelayFeeTooHigh(format!(
+                "estimated fee {} is above the relayer max fee {}",
+                estimate.overall_fee, self.max_fee
+            )));
+        };
+
+        // explicit bounds, so the transaction is not estimated again with the default
+        // multipliers
+        let start = Instant::now();
+        let result = execution
+            .l1_gas(bounds.l1_gas)
+            .l1_gas_price(bounds.l1_gas_price)
+            .l2_gas(bounds.l2_gas)
+            .l2_gas_price(bounds.l2_gas_price)
+            .l1_data_gas(bounds.l1_data_gas)
+            .l1_data_gas_price(bounds.l1_data_gas_price)
+            .tip(0)
+            .send()
+            .await;
+        metrics::record_rpc(
+            chain_id,
+            "add_invoke_transaction",
+            start,
+            !matches!(&result, Err(AccountError::Provider(error)) if metrics::is_rpc_failure(error)),
+        );
+
+        match result {
+            Ok(result) => {
+                *next_nonce = Some(nonce + Felt::ONE);
+                Ok(result.transaction_hash)
+            }
+            Err(error) => {
+                *next_nonce = None;
+                Err(Errors::RelayFailed(format!(
+                    "relayer {:#x}: {error}",
+                    self.address
+                )))
+            }
+        }
+    }
+}
+
+/// Resource bounds signed for a relayed transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ResourceBounds {
+    l1_gas: u64,
+    l1_gas_price: u128,
+    l2_gas: u64,
+    l2_gas_price: u128,
+    l1_data_gas: u64,
+    l1_data_gas_price: u128,
+}
+
+impl ResourceBounds {
+    /// Bounds of 1.5 times the estimated amounts and prices when their max fee fits
+    /// in `max_fee`, the estimate itself otherwise, `None` when even the estimate is
+    /// above `max_fee`.
+    fn within(estimate: &FeeEstimate, max_fee: u128) -> Option<ResourceBounds> {
+        let margin = |value: u128| value.saturating_add(value / 2);
+        let with_margin = ResourceBounds {
+            l1_gas: margin(estimate.l1_gas_consumed.into()).try_into().ok()?,
+            l1_gas_price: margin(estimate.l1_gas_price),
+            l2_gas: margin(estimate.l2_gas_consumed.into()).try_into().ok()?,
+            l2_gas_price: margin(estimate.l2_gas_price),
+            l1_data_gas: margin(estimate.l1_data_gas_consumed.into())
+                .try_into()
+                .ok()?,
+            l1_data_gas_price: margin(estimate.l1_data_gas_price),
+        };
+        let exact = ResourceBounds {
+            l1_gas: estimate.l1_gas_consumed,
+            l1_gas_price: estimate.l1_gas_price,
+            l2_gas: estimate.l2_gas_consumed,
+            l2_gas_price: estimate.l2_gas_price,
+            l1_data_gas: estimate.l1_data_gas_consumed,
+            l1_data_gas_price: estimate.l1_data_gas_price,
+        };
+
+        [with_margin, exact]
+            .into_iter()
+            .find(|bounds| bounds.max_fee().is_some_and(|fee| fee <= max_fee))
+    }
+
+    /// Highest fee the transaction may be charged, `None` on overflow.
+    fn max_fee(&self) -> Option<u128> {
+        u128::from(self.l1_gas)
+            .checked_mul(self.l1_gas_price)?
+            .checked_add(u128::from(self.l2_gas).checked_mul(self.l2_gas_price)?)?
+            .checked_add(u128::from(self.l1_data_gas).checked_mul(self.l1_data_gas_price)?)
+    }
+}
+
+/// Relayers of a chain, handed out in turn.
+#[derive(Clone, Default)]
+pub struct RelayerPool {
+    relayers: Vec<Relayer>,
+    next: Arc<AtomicUsize>,
+}
+
+impl RelayerPool {
+    pub fn new(relayers: Vec<Relayer>) -> Self {
+        RelayerPool {
+            relayers,
+            next: Arc::default(),
+        }
+    }
+
+    /// Returns the next relayer, `None` when the chain has none.
+    pub fn pick(&self) -> Option<&Relayer> {
+        if self.relayers.is_empty() {
+            return None;
+        }
+
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.relayers.len();
+        self.relayers.get(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use starknet::core::types::PriceUnit;
+    use starknet::macros::felt;
+    use starknet::signers::SigningKey;
+
+    fn relayer(address: Felt) -> Relayer {
+        Relayer::new(
+            address,
+            LocalWallet::from(SigningKey::from_secret_scalar(felt!("0x111"))),
+            u128::MAX,
+        )
+    }
+
+    #[test]
+    fn relayers_are_picked_in_turn() {
+        assert!(RelayerPool::default().pick().is_none());
+
+        let pool = RelayerPool::new(vec![relayer(felt!("0xa")), relayer(felt!("0xb"))]);
+        let picked: Vec<_> = (0..4).map(|_| pool.pick().unwrap().address).collect();
+
+        assert_eq!(
+            picked,
+            vec![felt!("0xa"), felt!("0xb"), felt!("0xa"), felt!("0xb")]
+        );
+    }
+
+    #[test]
+    fn resource_bounds_fit_in_max_fee() {
+        let estimate = FeeEstimate {
+            l1_gas_consumed: 0,
+            l1_gas_price: 10,
+            l2_gas_consumed: 1000,
+            l2_gas_price: 10,
+            l1_data_gas_consumed: 100,
+            l1_data_gas_price: 10,
+            overall_fee: 11_000,
+            unit: PriceUnit::Fri,
+        };
+
+        // 1.5 times the amounts and prices
+        let bounds = ResourceBounds::within(&estimate, 1_000_000).unwrap();
+        assert_eq!((bounds.l2_gas, bounds.l2_gas_price), (1500, 15));
+        assert_eq!(bounds.max_fee(), Some(24_750));
+
+        // the estimate itself when the margin doesn't fit
+        let bounds = ResourceBounds::within(&estimate, 20_000).unwrap();
+        assert_eq!((bounds.l2_gas, bounds.l2_gas_price), (1000, 10));
+        assert_eq!(bounds.max_fee(), Some(11_000));
+
+        assert!(ResourceBounds::within(&estimate, 10_999).is_none());
+    }
+}
@@ -0,0 +1,278 @@
+use anyhow::{anyhow, bail, Context};
+use num::{BigUint, Num, Zero};
+use serde::Deserialize;
+use starknet::core::types::Felt;
+use starknet::signers::SigningKey;
+use std::path::PathBuf;
+
+/// Order of the Stark curve, VRF secret scalars must be in `[1, EC_ORDER)`.
+pub const EC_ORDER: &str = "800000000000010ffffffffffffffffb781126dcae7b2321e66a241adc64d2f";
+
+/// Parses a VRF secret scalar given as a hex (`0x` prefixed) or decimal string.
+///
+/// The value must be non-zero and strictly lower than the Stark curve order.
+pub fn parse_vrf_secret_key(value: &str) -> Result<Felt, String> {
+    let value = value.trim();
+
+    let scalar = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => BigUint::from_str_radix(hex, 16),
+        None => BigUint::from_str_radix(value, 10),
+    }
+    .map_err(|_| "secret key must be a hex (0x prefixed) or decimal felt".to_string())?;
+
+    if scalar.is_zero() {
+        return Err("secret key must not be zero".to_string());
+    }
+
+    let order = BigUint::from_str_radix(EC_ORDER, 16).expect("valid curve order");
+    if scalar >= order {
+        return Err("secret key must be lower than the Stark curve order".to_string());
+    }
+
+    Ok(Felt::from_bytes_be_slice(&scalar.to_bytes_be()))
+}
+
+/// Parses an account private key given as a hex string.
+pub fn parse_account_private_key(value: &str) -> Result<Felt, String> {
+    let private_key = Felt::from_hex(value.trim())
+        .map_err(|_| "account private key must be a hex felt".to_string())?;
+
+    if private_key == Felt::ZERO {
+        return Err("account private key must not be zero".to_string());
+    }
+
+    Ok(private_key)
+}
+
+/// Where a secret is read from at startup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretSource {
+    /// Secret passed inline, either as a CLI flag or through an environment variable.
+    Value(String),
+    /// Path to a file holding the secret.
+    File(PathBuf),
+    /// Path to a Starknet JSON keystore, the password is read from `password_file`
+    /// or prompted for when `None`.
+    Keystore {
+        path: PathBuf,
+        password_file: Option<PathBuf>,
+    },
+}
+
+/// Options describing a secret, at most one source may be set.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecretOptions {
+    pub value: Option<String>,
+    pub file: Option<PathBuf>,
+    pub keystore: Option<PathBuf>,
+    pub keystore_password_file: Option<PathBuf>,
+}
+
+impl SecretOptions {
+    pub fn is_set(&self) -> bool {
+        self.value.is_some()
+            || self.file.is_some()
+            || self.keystore.is_some()
+            || self.keystore_password_file.is_some()
+    }
+
+    /// Returns the single configured source, `name` is the flag prefix used in error messages.
+    pub fn source(&self, name: &str) -> anyhow::Result<Option<SecretSource>> {
+        let mut sources = vec![];
+
+        if let Some(value) = &self.value {
+            sources.push(SecretSource::Value(value.clone()));
+        }
+        if let Some(file) = &self.file {
+            sources.push(SecretSource::File(file.clone()));
+        }
+        if let Some(path) = &self.keystore {
+            sources.push(SecretSource::Keystore {
+                path: path.clone(),
+                password_file: self.keystore_password_file.clone(),
+            });
+        }
+
+        if self.keystore.is_none() && self.keystore_password_file.is_some() {
+            bail!("--{name}-keystore-password-file requires --{name}-keystore");
+        }
+
+        if sources.len() > 1 {
+            bail!("only one of --{name}, --{name}-file or --{name}-keystore may be set");
+        }
+
+        Ok(sources.pop())
+    }
+}
+
+impl SecretSource {
+    /// Reads the raw secret from its source.
+    pub fn read(&self) -> anyhow::Result<String> {
+        match self {
+            SecretSource::Value(value) => Ok(value.trim().to_string()),
+            SecretSource::File(path) => {
+                let value = std::fs::read_to_string(path)
+                    .with_context(|| format!("failed to read secret file {}", path.display()))?;
+                Ok(value.trim().to_string())
+            }
+            SecretSource::Keystore {
+                path,
+                password_file,
+            } => {
+                let password = match password_file {
+                    Some(password_file) => std::fs::read_to_string(password_file)
+                        .with_context(|| {
+                            format!(
+                                "failed to read keystore password file {}",
+                                password_file.display()
+                            )
+                        })?
+                        .trim_end_matches(['\r', '\n'])
+                        .to_string(),
+                    None => rpassword::prompt_password(format!(
+                        "Enter password for keystore {}: ",
+                        path.display()
+                    ))
+                    .context("failed to read keystore password")?,
+                };
+
+                let signing_key = SigningKey::from_keystore(path, &password)
+                    .map_err(|e| anyhow!("failed to decrypt keystore {}: {e}", path.display()))?;
+
+                Ok(signing_key.secret_scalar().to_hex_string())
+            }
+        }
+    }
+
+    /// Reads the secret and parses it with `parse`.
+    pub fn load(&self, parse: fn(&str) -> Result<Felt, String>) -> anyhow::Result<Felt> {
+        let value = self.read()?;
+        parse(&value).map_err(|e| anyhow!(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use starknet::macros::felt;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("vrf-server-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn parses_decimal_and_hex() {
+        assert_eq!(parse_vrf_secret_key("420").unwrap(), felt!("0x1a4"));
+        assert_eq!(parse_vrf_secret_key("0x1a4").unwrap(), felt!("0x1a4"));
+        assert_eq!(parse_vrf_secret_key("0X1A4").unwrap(), felt!("0x1a4"));
+    }
+
+    #[test]
+    fn parses_full_width_scalar() {
+        let secret_key = parse_vrf_secret_key(
+            "0x7ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+        )
+        .unwrap();
+        assert_eq!(
+            secret_key.to_biguint().to_string(),
+            "3618502788666131106986593281521497120414687020801267626233049500247285301247"
+        );
+    }
+
+    #[test]
+    fn rejects_zero() {
+        assert!(parse_vrf_secret_key("0").is_err());
+        assert!(parse_vrf_secret_key("0x0").is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_range() {
+        assert!(parse_vrf_secret_key(&format!("0x{EC_ORDER}")).is_err());
+        assert!(parse_vrf_secret_key(
+            "0x800000000000011000000000000000000000000000000000000000000000001"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        assert!(parse_vrf_secret_key("").is_err());
+        assert!(parse_vrf_secret_key("0xzz").is_err());
+        assert!(parse_vrf_secret_key("-1").is_err());
+    }
+
+    #[test]
+    fn parses_account_private_key() {
+        assert_eq!(parse_account_private_key("0x111").unwrap(), felt!("0x111"));
+        assert!(parse_account_private_key("0x0").is_err());
+        assert!(parse_account_private_key("not_a_key").is_err());
+    }
+
+    #[test]
+    fn secret_sources_are_mutually_exclusive() {
+        let options = SecretOptions {
+            value: Some("0x1a4".into()),
+            file: Some("secret.txt".into()),
+            ..Default::default()
+        };
+        assert!(options.source("secret-key").is_err());
+
+        let options = SecretOptions {
+            keystore_password_file: Some("password.txt".into()),
+            ..Default::default()
+        };
+        assert!(options.source("secret-key").is_err());
+
+        assert_eq!(SecretOptions::default().source("secret-key").unwrap(), None);
+    }
+
+    #[test]
+    fn loads_secret_from_file() {
+        let path = temp_path("secret.txt");
+        std::fs::write(&path, "0x1a4\n").unwrap();
+
+        let options = SecretOptions {
+            file: Some(path.clone()),
+            ..Default::default()
+        };
+        let secret_key = options
+            .source("secret-key")
+            .unwrap()
+            .unwrap()
+            .load(parse_vrf_secret_key)
+            .unwrap();
+        assert_eq!(secret_key, felt!("0x1a4"));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn loads_secret_from_keystore() {
+        let keystore = temp_path("keystore.json");
+        let password_file = temp_path("keystore-password.txt");
+
+        SigningKey::from_secret_scalar(felt!("0x111"))
+            .save_as_keystore(&keystore, "password")
+            .unwrap();
+        std::fs::write(&password_file, "password\n").unwrap();
+
+        let source = SecretSource::Keystore {
+            path: keystore.clone(),
+            password_file: Some(password_file.clone()),
+        };
+        assert_eq!(
+            source.load(parse_account_private_key).unwrap(),
+            felt!("0x111")
+        );
+
+        std::fs::write(&password_file, "wrong").unwrap();
+        assert!(source.load(parse_account_private_key).is_err());
+
+        std::fs::remove_file(keystore).unwrap();
+        std::fs::remove_file(password_file).unwrap();
+    }
+}
@@ -0,0 +1,567 @@
+//! Bundles handed out by the server, tracked until they land, revert or expire.
+//!
+//! Bundles are identified by their outer nonce, relayed ones also by their
+//! transaction hash. Relayed bundles are followed through the receipt of their
+//! transaction. Returned bundles are submitted by someone else, so only the outer
+//! nonce is known: they landed once the VRF account marks it as used, and expired
+//! when their `execute_before` passes first. Failed outside executions revert as a
+//! whole and leave the nonce unused, so their revert reason is only known when
+//! relayed.
+//!
+//! At most `tracking.max_bundles` bundles are kept, the oldest ones are dropped
+//! first. Pending bundles are checked a few at a time.
+//!
+//! A relayed bundle that reverts releases its `Source::Nonce` reservation, so the
+//! next requests of the address don't queue behind a bundle that won't land.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use starknet::core::types::{
+    BlockId, BlockTag, ExecutionResult, Felt, FunctionCall, StarknetError,
+};
+use starknet::macros::selector;
+use starknet::providers::jsonrpc::HttpTransport;
+use starknet::providers::{JsonRpcClient, Provider, ProviderError};
+use tokio::task::JoinSet;
+use tracing::warn;
+
+use crate::metrics;
+use crate::state::nonces::{NonceKey, NonceReservations};
+
+/// Seconds after `expires_at` a bundle whose checks fail is given up as expired.
+const EXPIRED_GRACE_PERIOD: u64 = 60;
+/// Pending bundles checked at once by a poll.
+const POLL_CONCURRENCY: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BundleStatus {
+    /// Handed out or submitted, not seen on chain yet.
+    Pending,
+    Succeeded,
+    Reverted {
+        revert_reason: String,
+    },
+    /// `execute_before` passed before the bundle landed.
+    Expired,
+}
+
+impl BundleStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            BundleStatus::Pending => "pending",
+            BundleStatus::Succeeded => "succeeded",
+            BundleStatus::Reverted { .. } => "reverted",
+            BundleStatus::Expired => "expired",
+        }
+    }
+}
+
+/// Version of the outer outside execution, which sets the form of its nonce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BundleVersion {
+    /// Felt nonce.
+    V2,
+    /// `(channel, mask)` nonce, the outer nonce is the channel with the first bit.
+    V3,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackedBundle {
+    /// Nonce of the VRF account outside execution.
+    pub nonce: Felt,
+    pub chain_id: Felt,
+    pub vrf_account_address: Felt,
+    pub version: BundleVersion,
+    /// Set when the bundle was relayed by the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction_hash: Option<Felt>,
+    /// `execute_before` of the outer outside execution.
+    pub expires_at: u64,
+    #[serde(flatten)]
+    pub status: BundleStatus,
+}
+
+#[derive(Debug)]
+struct Entry {
+    bundle: TrackedBundle,
+    provider: JsonRpcClient<HttpTransport>,
+    tracked_at: Instant,
+    settled_at: Option<u64>,
+    /// `Source::Nonce` nonce reserved for the bundle.
+    reservation: Option<(NonceKey, Felt)>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    bundles: HashMap<Felt, Entry>,
+    /// Outer nonce of the relayed bundles, by transaction hash.
+    transactions: HashMap<Felt, Felt>,
+    /// Outer nonces in the order they were tracked, oldest first.
+    order: VecDeque<Felt>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BundleTracker {
+    entries: Arc<Mutex<Entries>>,
+    nonces: NonceReservations,
+    /// Seconds settled bundles are kept.
+    retention: u64,
+    /// Bundles kept at most.
+    max_bundles: usize,
+}
+
+impl BundleTracker {
+    pub fn new(nonces: NonceReservations, retention: u64, max_bundles: usize) -> Self {
+        BundleTracker {
+            entries: Arc::default(),
+            nonces,
+            retention,
+            max_bundles,
+        }
+    }
+
+    /// Starts tracking `bundle` with the provider it was built with and the
+    /// `Source::Nonce` nonce reserved for it.
+    ///
+    /// Bundles sharing an outer nonce can't both execute and are tracked once, a
+    /// relayed bundle adds its transaction hash to the tracked one.
+    pub fn track(
+        &self,
+        bundle: TrackedBundle,
+        provider: JsonRpcClient<HttpTransport>,
+        reservation: Option<(NonceKey, Felt)>,
+    ) {
+        let mut entries = self.entries.lock().unwrap();
+
+        if let Some(transaction_hash) = bundle.transaction_hash {
+            entries.transactions.insert(transaction_hash, bundle.nonce);
+        }
+
+        match entries.bundles.get_mut(&bundle.nonce) {
+            Some(entry) => {
+                if entry.bundle.transaction_hash.is_none() {
+                    entry.bundle.transaction_hash = bundle.transaction_hash;
+                    entry.provider = provider;
+                }
+                entry.reservation = entry.reservation.or(reservation);
+            }
+            None => {
+                metrics::record_bundle_tracked(bundle.chain_id);
+                entries.order.push_back(bundle.nonce);
+                entries.bundles.insert(
+                    bundle.nonce,
+                    Entry {
+                        bundle,
+                        provider,
+                        tracked_at: Instant::now(),
+                        settled_at: None,
+                        reservation,
+                    },
+                );
+                entries.evict(self.max_bundles);
+            }
+        }
+    }
+
+    /// Returns the bundle whose outer nonce or transaction hash is `id`.
+    pub fn get(&self, id: Felt) -> Option<TrackedBundle> {
+        let entries = self.entries.lock().unwrap();
+        let nonce = entries.transactions.get(&id).copied().unwrap_or(id);
+
+        entries
+            .bundles
+            .get(&nonce)
+            .map(|entry| entry.bundle.clone())
+    }
+
+    /// Returns the bundle `id` after checking it on chain when it is still pending.
+    pub async fn refresh(&self, id: Felt, now: u64) -> Option<TrackedBundle> {
+        let bundle = self.get(id)?;
+        if bundle.status == BundleStatus::Pending {
+            self.update(bundle.nonce, now).await;
+        }
+
+        self.get(id)
+    }
+
+    /// Checks every pending bundle on chain and drops the settled ones past their
+    /// retention.
+    pub async fn poll(&self, now: u64) {
+        let pending: Vec<Felt> = {
+            let mut entries = self.entries.lock().unwrap();
+            let retention = self.retention;
+            entries.bundles.retain(|_, entry| {
+                entry
+                    .settled_at
+                    .is_none_or(|settled_at| settled_at.saturating_add(retention) > now)
+            });
+            let Entries {
+                bundles,
+                transactions,
+                order,
+            } = &mut *entries;
+            transactions.retain(|_, nonce| bundles.contains_key(nonce));
+            order.retain(|nonce| bundles.contains_key(nonce));
+
+            bundles
+                .values()
+                .filter(|entry| entry.settled_at.is_none())
+                .map(|entry| entry.bundle.nonce)
+                .collect()
+        };
+
+        for batch in pending.chunks(POLL_CONCURRENCY) {
+            let mut updates = JoinSet::new();
+            for &nonce in batch {
+                let tracker = self.clone();
+                updates.spawn(async move { tracker.update(nonce, now).await });
+            }
+            updates.join_all().await;
+        }
+    }
+
+    /// Polls the pending bundles every `interval`.
+    pub async fn run(self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            self.poll(Utc::now().timestamp() as u64).await;
+        }
+    }
+
+    async fn update(&self, nonce: Felt, now: u64) {
+        let Some((bundle, provider)) = self
+            .entries
+            .lock()
+            .unwrap()
+            .bundles
+            .get(&nonce)
+            .map(|entry| (entry.bundle.clone(), entry.provider.clone()))
+        else {
+            return;
+        };
+
+        let status = match check(&bundle, &provider, now).await {
+            Ok(Some(status)) => status,
+            Ok(None) => return,
+            Err(error) => {
+                warn!("failed to check bundle {nonce:#x}: {error}");
+                // the RPC may be gone for good, stop checking once the bundle can't land
+                if bundle.expires_at.saturating_add(EXPIRED_GRACE_PERIOD) > now {
+                    return;
+                }
+                BundleStatus::Expired
+            }
+        };
+
+        self.settle(nonce, status, now);
+    }
+
+    /// Sets the status of the pending bundle `nonce`, and releases its reservation
+    /// when it reverted: the outer nonce is left unused and the address nonce isn't
+    /// bumped.
+    fn settle(&self, nonce: Felt, status: BundleStatus, now: u64) {
+        let released = {
+            let mut entries = self.entries.lock().unwrap();
+            let Some(entry) = entries.bundles.get_mut(&nonce) else {
+                return;
+            };
+            if entry.settled_at.is_some() {
+                return;
+            }
+
+            metrics::record_bundle_settled(
+                entry.bundle.chain_id,
+                status.label(),
+                entry.tracked_at.elapsed(),
+            );
+            let reverted = matches!(status, BundleStatus::Reverted { .. });
+            entry.bundle.status = status;
+            entry.settled_at = Some(now);
+            entry.reservation.filter(|_| reverted)
+        };
+
+        if let Some((key, nonce)) = released {
+            self.nonces.release(key, nonce);
+        }
+    }
+}
+
+impl Entries {
+    /// Drops the oldest bundles until at most `max_bundles` are left.
+    fn evict(&mut self, max_bundles: usize) {
+        while self.bundles.len() > max_bundles {
+            let Some(nonce) = self.order.pop_front() else {
+                return;
+            };
+            // already dropped after its retention
+            let Some(entry) = self.bundles.remove(&nonce) else {
+                continue;
+            };
+
+            if entry.settled_at.is_none() {
+                metrics::record_bundle_evicted(entry.bundle.chain_id);
+            }
+            if let Some(transaction_hash) = entry.bundle.transaction_hash {
+                self.transactions.remove(&transaction_hash);
+            }
+        }
+    }
+}
+
+/// Returns the status of `bundle` once it landed, reverted or expired, `None` while
+/// it is pending.
+async fn check(
+    bundle: &TrackedBundle,
+    provider: &JsonRpcClient<HttpTransport>,
+    now: u64,
+) -> Result<Option<BundleStatus>, ProviderError> {
+    let expired = bundle.expires_at <= now;
+
+    if let Some(transaction_hash) = bundle.transaction_hash {
+        let start = Instant::now();
+        let receipt = provider.get_transaction_receipt(transaction_hash).await;
+        metrics::record_rpc(
+            bundle.chain_id,
+            "get_transaction_receipt",
+            start,
+            !matches!(&receipt, Err(error) if metrics::is_rpc_failure(error)),
+        );
+
+        return match receipt {
+            Ok(receipt) => Ok(Some(match receipt.receipt.execution_result() {
+                ExecutionResult::Succeeded => BundleStatus::Succeeded,
+                ExecutionResult::Reverted { reason } => BundleStatus::Reverted {
+                    revert_reason: short_revert_reason(reason),
+                },
+            })),
+            // dropped by the sequencer
+            Err(ProviderError::StarknetError(StarknetError::TransactionHashNotFound)) => {
+                Ok(expired.then_some(BundleStatus::Expired))
+            }
+            Err(error) => Err(error),
+        };
+    }
+
+    let (method, call) = nonce_check_call(bundle);
+    let start = Instant::now();
+    let valid = provider
+        .call(call, BlockId::Tag(BlockTag::PreConfirmed))
+        .await;
+    metrics::record_rpc(bundle.chain_id, method, start, valid.is_ok());
+
+    // the nonce is only used once the bundle landed
+    let used = valid?.first() == Some(&Felt::ZERO);
+    Ok(if used {
+        Some(BundleStatus::Succeeded)
+    } else {
+        expired.then_some(BundleStatus::Expired)
+    })
+}
+
+/// Returns the call checking whether the outer nonce of `bundle` is still unused, in
+/// the form of its outside execution version, with its name.
+fn nonce_check_call(bundle: &TrackedBundle) -> (&'static str, FunctionCall) {
+    let (method, entry_point_selector, calldata) = match bundle.version {
+        BundleVersion::V2 => (
+            "is_valid_outside_execution_nonce",
+            selector!("is_valid_outside_execution_nonce"),
+            vec![bundle.nonce],
+        ),
+        BundleVersion::V3 => (
+            "is_valid_outside_execution_v3_nonce",
+            selector!("is_valid_outside_execution_v3_nonce"),
+            vec![bundle.nonce, Felt::ONE],
+        ),
+    };
+
+    (
+        method,
+        FunctionCall {
+            contract_address: bundle.vrf_account_address,
+            entry_point_selector,
+            calldata,
+        },
+    )
+}
+
+/// Receipts wrap the panic of the failing contract in the error of every frame of the
+/// call stack, the innermost one (`VrfProvider: not fulfilled`) is the first short
+/// string of the failure reason. Falls back to the whole reason.
+fn short_revert_reason(reason: &str) -> String {
+    reason
+        .split_once("('")
+        .and_then(|(_, rest)| rest.split_once("')"))
+        .map(|(short, _)| short.to_string())
+        .unwrap_or_else(|| reason.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use starknet::macros::felt;
+    use url::Url;
+
+    fn bundle(nonce: Felt, transaction_hash: Option<Felt>) -> TrackedBundle {
+        TrackedBundle {
+            nonce,
+            chain_id: felt!("0x57505f4b4154414e41"),
+            vrf_account_address: felt!("0x111"),
+            version: BundleVersion::V2,
+            transaction_hash,
+            expires_at: 700,
+            status: BundleStatus::Pending,
+        }
+    }
+
+    fn provider() -> JsonRpcClient<HttpTransport> {
+        JsonRpcClient::new(HttpTransport::new(
+            Url::parse("http://localhost:5050").unwrap(),
+        ))
+    }
+
+    #[test]
+    fn bundles_are_found_by_nonce_or_transaction_hash() {
+        let tracker = BundleTracker::new(NonceReservations::default(), 3600, 100);
+        tracker.track(bundle(felt!("0x1"), None), provider(), None);
+        assert!(tracker.get(felt!("0xa")).is_none());
+
+        // relaying the returned bundle adds its transaction hash
+        tracker.track(bundle(felt!("0x1"), Some(felt!("0xa"))), provider(), None);
+
+        let tracked = tracker.get(felt!("0xa")).unwrap();
+        assert_eq!(tracked.nonce, felt!("0x1"));
+        assert_eq!(tracked.transaction_hash, Some(felt!("0xa")));
+        assert_eq!(
+            tracker.get(felt!("0x1")).unwrap().transaction_hash,
+            Some(felt!("0xa"))
+        );
+        assert!(tracker.get(felt!("0x2")).is_none());
+    }
+
+    #[test]
+    fn oldest_bundles_are_evicted() {
+        let tracker = BundleTracker::new(NonceReservations::default(), 3600, 2);
+        tracker.track(bundle(felt!("0x1"), Some(felt!("0xa"))), provider(), None);
+        tracker.track(bundle(felt!("0x2"), None), provider(), None);
+        tracker.track(bundle(felt!("0x3"), None), provider(), None);
+
+        assert!(tracker.get(felt!("0x1")).is_none());
+        assert!(tracker.get(felt!("0xa")).is_none());
+        assert!(tracker.get(felt!("0x2")).is_some());
+        assert!(tracker.get(felt!("0x3")).is_some());
+        assert!(tracker.entries.lock().unwrap().transactions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn unreachable_bundles_expire() {
+        let tracker = BundleTracker::new(NonceReservations::default(), 3600, 100);
+        let unreachable = JsonRpcClient::new(HttpTransport::new(
+            Url::parse("http://127.0.0.1:1").unwrap(),
+        ));
+        tracker.track(bundle(felt!("0x1"), None), unreachable.clone(), None);
+        tracker.track(bundle(felt!("0x2"), Some(felt!("0xa"))), unreachable, None);
+
+        tracker.poll(700).await;
+        assert_eq!(
+            tracker.get(felt!("0x1")).unwrap().status,
+            BundleStatus::Pending
+        );
+
+        tracker.poll(700 + EXPIRED_GRACE_PERIOD).await;
+        assert_eq!(
+            tracker.get(felt!("0x1")).unwrap().status,
+            BundleStatus::Expired
+        );
+        assert_eq!(
+            tracker.get(felt!("0xa")).unwrap().status,
+            BundleStatus::Expired
+        );
+
+        // and are dropped after their retention
+        tracker.poll(700 + EXPIRED_GRACE_PERIOD + 3600).await;
+        assert!(tracker.get(felt!("0x1")).is_none());
+        assert!(tracker.get(felt!("0xa")).is_none());
+    }
+
+    #[test]
+    fn reverted_bundles_release_their_reservation() {
+        let nonces = NonceReservations::default();
+        let tracker = BundleTracker::new(nonces.clone(), 3600, 100);
+        let key = NonceKey::new(felt!("0x57505f4b4154414e41"), felt!("0x111"), felt!("0xb"));
+
+        for (outer_nonce, transaction_hash) in
+            [(felt!("0x1"), felt!("0xa")), (felt!("0x2"), felt!("0xb"))]
+        {
+            let reservation = nonces.reserve(key, felt!("0x5"), 100, 700, outer_nonce);
+            tracker.track(
+                bundle(outer_nonce, Some(transaction_hash)),
+                provider(),
+                Some((reservation.key(), reservation.nonce())),
+            );
+            reservation.keep();
+        }
+        assert_eq!(nonces.pending_nonce(key, felt!("0x5"), 100), felt!("0x7"));
+
+        tracker.settle(felt!("0x1"), BundleStatus::Succeeded, 100);
+        assert_eq!(nonces.pending_nonce(key, felt!("0x5"), 100), felt!("0x7"));
+
+        tracker.settle(
+            felt!("0x2"),
+            BundleStatus::Reverted {
+                revert_reason: "VrfProvider: not fulfilled".to_string(),
+            },
+            100,
+        );
+        assert_eq!(nonces.pending_nonce(key, felt!("0x5"), 100), felt!("0x6"));
+    }
+
+    #[test]
+    fn nonce_check_follows_version() {
+        let mut tracked = bundle(felt!("0x1"), None);
+        let (method, call) = nonce_check_call(&tracked);
+        assert_eq!(method, "is_valid_outside_execution_nonce");
+        assert_eq!(call.calldata, vec![felt!("0x1")]);
+
+        tracked.version = BundleVersion::V3;
+        let (method, call) = nonce_check_call(&tracked);
+        assert_eq!(method, "is_valid_outside_execution_v3_nonce");
+        assert_eq!(
+            call.entry_point_selector,
+            selector!("is_valid_outside_execution_v3_nonce")
+        );
+        assert_eq!(call.calldata, vec![felt!("0x1"), Felt::ONE]);
+    }
+
+    #[test]
+    fn status_is_flattened() {
+        let mut tracked = bundle(felt!("0x1"), Some(felt!("0xa")));
+        tracked.status = BundleStatus::Reverted {
+            revert_reason: "VrfProvider: not fulfilled".to_string(),
+        };
+
+        let value = serde_json::to_value(&tracked).unwrap();
+        assert_eq!(value["status"], "reverted");
+        assert_eq!(value["revert_reason"], "VrfProvider: not fulfilled");
+    }
+
+    #[test]
+    fn revert_reason_is_the_innermost_error() {
+        let reason = "Transaction execution has failed:\n\
+            0: Error in the called contract (contract address: 0x1, class hash: 0x2, selector: 0x3):\n\
+            Execution failed. Failure reason:\n\
+            (0x5672665072 ('VrfProvider: not fulfilled'), 0x454e545259504f494e545f4641494c4544 ('ENTRYPOINT_FAILED')).\n";
+        assert_eq!(short_revert_reason(reason), "VrfProvider: not fulfilled");
+
+        assert_eq!(
+            short_revert_reason("SRC9: duplicated nonce\n"),
+            "SRC9: duplicated nonce"
+        );
+    }
+}
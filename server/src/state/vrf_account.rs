@@ -0,0 +1,170 @@
+//! On-chain state of the VRF accounts.
+
+use std::time::Duration;
+
+use anyhow::bail;
+use starknet::core::types::{BlockId, BlockTag, FunctionCall, StarknetError};
+use starknet::macros::selector;
+use starknet::providers::jsonrpc::HttpTransport;
+use starknet::providers::{JsonRpcClient, Provider, ProviderError};
+use starknet_crypto::Felt;
+use tracing::{error, info, warn};
+
+use crate::routes::outside_execution::simulation::revert_reason;
+use crate::state::chains::ChainRegistry;
+
+/// Reads the VRF public key `[x, y]` stored by `vrf_account_address`.
+///
+/// VRF accounts expose it with `get_vrf_public_key`, the legacy `VrfProvider`
+/// contract with `get_public_key`, which is only tried when the former is missing.
+pub async fn fetch_vrf_public_key(
+    provider: &JsonRpcClient<HttpTransport>,
+    vrf_account_address: Felt,
+) -> Result<Vec<Felt>, ProviderError> {
+    let call = |entry_point_selector| {
+        provider.call(
+            FunctionCall {
+                contract_address: vrf_account_address,
+                entry_point_selector,
+                calldata: vec![],
+            },
+            BlockId::Tag(BlockTag::PreConfirmed),
+        )
+    };
+
+    match call(selector!("get_vrf_public_key")).await {
+        Err(ProviderError::StarknetError(error)) if is_entrypoint_not_found(&error) => {
+            call(selector!("get_public_key"))
+                .await
+                .map_err(|legacy_error| {
+                    warn!("get_public_key failed: {legacy_error}");
+                    ProviderError::StarknetError(error)
+                })
+        }
+        result => result,
+    }
+}
+
+/// Nodes report a missing entrypoint either with the dedicated error or as a contract
+/// error, `ENTRYPOINT_NOT_FOUND` for Cairo 1 contracts.
+fn is_entrypoint_not_found(error: &StarknetError) -> bool {
+    match error {
+        StarknetError::EntrypointNotFound => true,
+        StarknetError::ContractError(data) => {
+            let reason = revert_reason(&data.revert_error);
+            reason.contains("ENTRYPOINT_NOT_FOUND")
+                || (reason.contains("Entry point") && reason.contains("not found"))
+        }
+        _ => false,
+    }
+}
+
+/// Ensures the VRF account of every chain stores the public key of the configured
+/// VRF secret key, `submit_random` reverts with `VrfProvider: invalid proof` otherwise.
+///
+/// Chains without an `rpc_url` are logged and skipped. Mismatches and chains whose
+/// public key can't be read (RPC unreachable or timing out, account missing) are errors.
+pub async fn check_vrf_public_keys(
+    chains: &ChainRegistry,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let mut mismatches = vec![];
+    let mut unchecked = vec![];
+
+    for chain in chains.iter() {
+        let Some(rpc_url) = &chain.rpc_url else {
+            warn!(
+                "chain {}: no rpc_url, skipping the VRF public key check",
+                chain.name
+            );
+            continue;
+        };
+
+        let provider = JsonRpcClient::new(HttpTransport::new(rpc_url.clone()));
+        let vrf_account_address = chain.vrf_account_address.0;
+        let expected = chain.vrf_key.public_key_felts();
+
+        match tokio::time::timeout(
+            timeout,
+            fetch_vrf_public_key(&provider, vrf_account_address),
+        )
+        .await
+        {
+            Ok(Ok(public_key)) if public_key == expected => {
+                info!(
+                    "chain {}: VRF account {vrf_account_address:#x} public key matches",
+                    chain.name
+                );
+            }
+            Ok(Ok(public_key)) => {
+                error!(
+                    "chain {}: VRF account {vrf_account_address:#x} public key {} does not match the configured secret key, expected {}",
+                    chain.name,
+                    format_public_key(&public_key),
+                    format_public_key(&expected),
+                );
+                mismatches.push(chain.name.clone());
+            }
+            Ok(Err(e)) => {
+                error!(
+                    "chain {}: failed to read the public key of VRF account {vrf_account_address:#x}: {e}",
+                    chain.name
+                );
+                unchecked.push(chain.name.clone());
+            }
+            Err(_) => {
+                error!("chain {}: reading the VRF public key timed out", chain.name);
+                unchecked.push(chain.name.clone());
+            }
+        }
+    }
+
+    if !mismatches.is_empty() {
+        bail!(
+            "VRF public key mismatch on chains {}, check the VRF secret key or pass --skip-public-key-check",
+            mismatches.join(", ")
+        );
+    }
+    if !unchecked.is_empty() {
+        bail!(
+            "could not check the VRF public key on chains {}, check their rpc_url or pass --skip-public-key-check",
+            unchecked.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+pub fn format_public_key(public_key: &[Felt]) -> String {
+    let felts: Vec<_> = public_key.iter().map(|felt| format!("{felt:#x}")).collect();
+    format!("({})", felts.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use starknet::core::types::{ContractErrorData, ContractExecutionError};
+
+    fn contract_error(message: &str) -> StarknetError {
+        StarknetError::ContractError(ContractErrorData {
+            revert_error: ContractExecutionError::Message(message.into()),
+        })
+    }
+
+    #[test]
+    fn only_missing_entrypoints_fall_back() {
+        assert!(is_entrypoint_not_found(&StarknetError::EntrypointNotFound));
+        assert!(is_entrypoint_not_found(&contract_error(
+            "Entry point EntryPointSelector(0x1) not found in contract."
+        )));
+        assert!(is_entrypoint_not_found(&contract_error(
+            "Execution failed. Failure reason: 0x454e545259504f494e545f4e4f545f464f554e44 ('ENTRYPOINT_NOT_FOUND')."
+        )));
+
+        assert!(!is_entrypoint_not_found(&contract_error(
+            "Execution failed. Failure reason: 0x4f7574206f6620676173 ('Out of gas')."
+        )));
+        assert!(!is_entrypoint_not_found(&StarknetError::ContractNotFound));
+        assert!(!is_entrypoint_not_found(&StarknetError::BlockNotFound));
+    }
+}
@@ -1,30 +1,47 @@
-use crate::{create_app, state::AppState, Args};
+use crate::{
+    config::Config,
+    create_app,
+    routes::outside_execution::{
+        build_outside_execution_v2,
+        signature::sign_outside_execution,
+        types::{self, ExecutionWindow, OutsideExecution, SignedOutsideExecution},
+        ANY_CALLER,
+    },
+    state::AppState,
+    Args,
+};
 use anyhow::{anyhow, Result};
 use axum_test::TestServer;
 use cainome_cairo_serde::{ClassHash, ContractAddress};
 use cairo_lang_starknet_classes::casm_contract_class::CasmContractClass;
 use cairo_lang_starknet_classes::contract_class::ContractClass;
+use chrono::Utc;
 use dojo_utils::TransactionWaiter;
 use katana_runner::RunnerCtx;
 use num::FromPrimitive;
 use starknet::{
-    accounts::{Account, SingleOwnerAccount},
+    accounts::{Account, ExecutionEncoding, SingleOwnerAccount},
     core::{
         types::{
             contract::{CompiledClass, SierraClass},
             Call, FlattenedSierraClass,
         },
-        utils::get_udc_deployed_address,
+        utils::{cairo_short_string_to_felt, get_udc_deployed_address},
     },
     macros::{felt, selector},
-    providers::{jsonrpc::HttpTransport, JsonRpcClient},
-    signers::LocalWallet,
+    providers::{jsonrpc::HttpTransport, JsonRpcClient, Provider},
+    signers::{LocalWallet, SigningKey},
 };
 use starknet_crypto::Felt;
 use std::{fs::File, path::PathBuf, sync::Arc};
 
 pub async fn new_test_server(args: &Args) -> TestServer {
-    let app_state = AppState::from_args(args).await;
+    let config = Config::load(args).unwrap();
+    new_test_server_with_config(config).await
+}
+
+pub async fn new_test_server_with_config(config: Config) -> TestServer {
+    let app_state = AppState::from_config(config).await.unwrap();
     let app = create_app(app_state).await;
 
     TestServer::builder()
@@ -48,6 +65,8 @@ pub const ACCOUNT_MOCK_PRIVATE_KEY: Felt =
     felt!("0x1c9053c053edf324aec366a34c6901b1095b07af69495bffec7d7fe21effb1b");
 
 pub const VRF_ACCOUNT_ARTIFACT: &str = "../target/dev/cartridge_vrf_VrfAccount.contract_class.json";
+pub const VRF_PROVIDER_ARTIFACT: &str =
+    "../target/dev/cartridge_vrf_VrfProvider.contract_class.json";
 pub const VRF_CONSUMER_ARTIFACT: &str =
     "../target/dev/cartridge_vrf_VrfConsumer.contract_class.json";
 
@@ -55,7 +74,7 @@ pub const VRF_ACCOUNT_PRIVATE_KEY: &str = "0x111";
 pub const VRF_ACCOUNT_PUBLIC_KEY: Felt =
     felt!("0x14584bef56c98fbb91aba84c20724937d5b5d2d6e5a49b60e6c3a19696fad5f");
 
-pub const VRF_SECRET_KEY: u64 = 420;
+pub const VRF_SECRET_KEY: Felt = felt!("0x1a4"); // 420
 pub const VRF_PUBLIC_KEY: [Felt; 2] = [
     felt!("0x66da5d53168d591c55d4c05f3681663ac51bcdccd5ca09e366b71b0c40ccff4"),
     felt!("0x6d3eb29920bf55195e5ec76f69e247c0942c7ef85f6640896c058ec75ca2232"),
@@ -127,6 +146,108 @@ pub async fn declare_and_deploy(
     (contract_address.into(), class_hash)
 }
 
+/// Serves only the katana chain, the default chains are not reachable from tests.
+pub fn katana_config(sequencer: &RunnerCtx, vrf_account_address: Felt, secret_key: Felt) -> Config {
+    let chain_id = cairo_short_string_to_felt("WP_KATANA").unwrap();
+    let args = Args::default()
+        .with_account_address(&vrf_account_address.to_hex_string())
+        .with_account_private_key(VRF_ACCOUNT_PRIVATE_KEY)
+        .with_secret_key(secret_key)
+        .with_chain(&format!("WP_KATANA={}", sequencer.url()));
+
+    let mut config = Config::load(&args).unwrap();
+    config.chains.retain(|_, chain| chain.chain_id == chain_id);
+    config
+}
+
+/// Signs an outside execution of `calls` by `account_address`, callable by anyone
+/// for `expiry` seconds, with a random nonce.
+pub async fn build_signed_outside_execution_v2(
+    account_address: Felt,
+    signer: LocalWallet,
+    chain_id: Felt,
+    calls: Vec<types::Call>,
+    expiry: u64,
+) -> SignedOutsideExecution {
+    let window = ExecutionWindow::from_now(Utc::now().timestamp() as u64, expiry);
+    let nonce = SigningKey::from_random().secret_scalar();
+    let outside_execution = build_outside_execution_v2(calls, window, ANY_CALLER, nonce);
+    build_signed_outside_execution(account_address, signer, chain_id, outside_execution).await
+}
+
+pub async fn build_signed_outside_execution(
+    account_address: Felt,
+    signer: LocalWallet,
+    chain_id: Felt,
+    outside_execution: OutsideExecution,
+) -> SignedOutsideExecution {
+    let signature =
+        sign_outside_execution(&outside_execution, chain_id, account_address, signer).await;
+
+    SignedOutsideExecution {
+        address: account_address,
+        outside_execution,
+        signature,
+    }
+}
+
+/// Deploys a VRF account funded with 10 STRK whose VRF public key is `VRF_PUBLIC_KEY`.
+pub async fn deploy_vrf_account(sequencer: &RunnerCtx) -> ContractAddress {
+    let account = sequencer.account(0);
+    let chain_id = sequencer.provider().chain_id().await.unwrap();
+
+    let (vrf_account_address, _) = declare_and_deploy(
+        sequencer,
+        VRF_ACCOUNT_ARTIFACT,
+        vec![VRF_ACCOUNT_PUBLIC_KEY],
+    )
+    .await;
+
+    let transfer_tx_result = account
+        .execute_v3(vec![Call {
+            to: STRK_ADDRESS,
+            selector: selector!("transfer"),
+            calldata: vec![
+                vrf_account_address.0,
+                Felt::from_u128(10 * 10_u128.pow(18)).unwrap(),
+                Felt::ZERO,
+            ],
+        }])
+        .send()
+        .await
+        .unwrap();
+    TransactionWaiter::new(transfer_tx_result.transaction_hash, sequencer.provider())
+        .await
+        .unwrap();
+
+    let vrf_account = SingleOwnerAccount::new(
+        sequencer.provider(),
+        LocalWallet::from_signing_key(SigningKey::from_secret_scalar(
+            Felt::from_hex(VRF_ACCOUNT_PRIVATE_KEY).unwrap(),
+        )),
+        vrf_account_address.0,
+        chain_id,
+        ExecutionEncoding::New,
+    );
+    let set_vrf_public_key_tx_result = vrf_account
+        .execute_v3(vec![Call {
+            to: vrf_account_address.0,
+            selector: selector!("set_vrf_public_key"),
+            calldata: VRF_PUBLIC_KEY.into(),
+        }])
+        .send()
+        .await
+        .unwrap();
+    TransactionWaiter::new(
+        set_vrf_public_key_tx_result.transaction_hash,
+        sequencer.provider(),
+    )
+    .await
+    .unwrap();
+
+    vrf_account_address
+}
+
 // from katana
 
 pub fn prepare_contract_declaration_params(
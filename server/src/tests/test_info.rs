@@ -1,10 +1,14 @@
-use crate::{routes::info::InfoResult, tests::setup::new_test_server, Args};
+use crate::{
+    routes::info::InfoResult,
+    tests::setup::{new_test_server, VRF_SECRET_KEY},
+    Args,
+};
 use katana_runner::RunnerCtx;
 
 #[tokio::test(flavor = "multi_thread")]
 #[katana_runner::test(accounts = 10)]
 async fn test_info(_sequencer: &RunnerCtx) {
-    let args = Args::default().with_secret_key(420);
+    let args = Args::default().with_secret_key(VRF_SECRET_KEY);
     let server = new_test_server(&args).await;
 
     let info = server.get("/info").await;
@@ -19,3 +23,20 @@ async fn test_info(_sequencer: &RunnerCtx) {
         "invalid public_key_y"
     );
 }
+
+#[tokio::test]
+async fn test_info_with_secret_key_file() {
+    let path = std::env::temp_dir().join(format!("vrf-server-{}-info-secret", std::process::id()));
+    std::fs::write(&path, "0x1a4\n").unwrap();
+
+    let args = Args::default().with_secret_key_file(path.to_str().unwrap());
+    let server = new_test_server(&args).await;
+
+    let result = server.get("/info").await.json::<InfoResult>();
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!(
+        result.public_key_x,
+        "0x66da5d53168d591c55d4c05f3681663ac51bcdccd5ca09e366b71b0c40ccff4"
+    );
+}
@@ -0,0 +1,38 @@
+use crate::{
+    tests::setup::{new_test_server, VRF_SECRET_KEY},
+    Args,
+};
+use serde_json::json;
+
+#[tokio::test]
+async fn test_metrics() {
+    let args = Args::default().with_secret_key(VRF_SECRET_KEY);
+    let server = new_test_server(&args).await;
+
+    server.get("/info").await;
+    server
+        .post("/proof")
+        .json(&json!({ "seed": ["0x1"] }))
+        .await;
+    server
+        .post("/proof")
+        .json(&json!({ "seed": ["0xzz"] }))
+        .expect_failure()
+        .await;
+
+    let metrics = server.get("/metrics").await.text();
+
+    // the recorder is shared by every test server, only check presence
+    for expected in [
+        r#"vrf_http_requests_total{method="GET",route="/info",status="200"}"#,
+        r#"vrf_http_requests_total{method="POST",route="/proof",status="400"}"#,
+        r#"vrf_http_request_duration_seconds_bucket{method="POST",route="/proof",status="200""#,
+        "vrf_proof_duration_seconds_bucket",
+        r#"vrf_errors_total{code="invalid_seed"}"#,
+    ] {
+        assert!(
+            metrics.contains(expected),
+            "missing {expected} in\n{metrics}"
+        );
+    }
+}
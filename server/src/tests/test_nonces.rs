@@ -0,0 +1,143 @@
+use crate::{
+    config::DEFAULT_OUTSIDE_EXECUTION_EXPIRY,
+    routes::outside_execution::{
+        context::RequestContext,
+        types::{get_calls, SignedOutsideExecution},
+        OutsideExecutionRequest, OutsideExecutionResult,
+    },
+    tests::setup::{
+        build_signed_outside_execution_v2, declare_and_deploy, deploy_vrf_account, katana_config,
+        new_test_server_with_config, ACCOUNT_MOCK_ARTIFACT, ACCOUNT_MOCK_PRIVATE_KEY,
+        ACCOUNT_MOCK_PUBLIC_KEY, VRF_CONSUMER_ARTIFACT, VRF_SECRET_KEY,
+    },
+};
+use dojo_utils::TransactionWaiter;
+use katana_runner::RunnerCtx;
+use starknet::{
+    accounts::Account,
+    core::{
+        types::{Call, ExecutionResult},
+        utils::cairo_short_string_to_felt,
+    },
+    macros::selector,
+    providers::Provider,
+    signers::{LocalWallet, SigningKey},
+};
+use starknet_crypto::{poseidon_hash_many, Felt};
+
+#[tokio::test(flavor = "multi_thread")]
+#[katana_runner::test(accounts = 10, chain_id = Felt::from_hex_unchecked("0x57505f4b4154414e41"))] // WP_KATANA
+async fn test_concurrent_requests_from_same_address(sequencer: &RunnerCtx) {
+    let chain_id = sequencer.provider().chain_id().await.unwrap();
+    let vrf_account_address = deploy_vrf_account(sequencer).await;
+
+    let (consumer_address, _) = declare_and_deploy(
+        sequencer,
+        VRF_CONSUMER_ARTIFACT,
+        vec![vrf_account_address.0],
+    )
+    .await;
+    let (user_address, _) = declare_and_deploy(
+        sequencer,
+        ACCOUNT_MOCK_ARTIFACT,
+        vec![ACCOUNT_MOCK_PUBLIC_KEY],
+    )
+    .await;
+    let user_signer =
+        LocalWallet::from_signing_key(SigningKey::from_secret_scalar(ACCOUNT_MOCK_PRIVATE_KEY));
+
+    let user_calls = vec![
+        Call {
+            to: vrf_account_address.0,
+            selector: selector!("request_random"),
+            calldata: vec![
+                consumer_address.0,
+                Felt::ZERO, // Source::Nonce
+                user_address.0,
+            ],
+        }
+        .into(),
+        Call {
+            to: consumer_address.0,
+            selector: selector!("dice"),
+            calldata: vec![],
+        }
+        .into(),
+    ];
+
+    let mut requests = vec![];
+    for _ in 0..3 {
+        let request = build_signed_outside_execution_v2(
+            user_address.0,
+            user_signer.clone(),
+            chain_id,
+            user_calls.clone(),
+            DEFAULT_OUTSIDE_EXECUTION_EXPIRY,
+        )
+        .await;
+
+        requests.push(OutsideExecutionRequest {
+            request,
+            context: RequestContext {
+                chain_id: cairo_short_string_to_felt("WP_KATANA").unwrap(),
+                rpc_url: Some(sequencer.url()),
+                executor: None,
+            },
+        });
+    }
+
+    let config = katana_config(sequencer, vrf_account_address.0, VRF_SECRET_KEY);
+    let server = new_test_server_with_config(config).await;
+
+    let (first, second) = tokio::join!(
+        server.post("/outside_execution").json(&requests[0]),
+        server.post("/outside_execution").json(&requests[1]),
+    );
+    let mut results = vec![
+        first.json::<OutsideExecutionResult>().result,
+        second.json::<OutsideExecutionResult>().result,
+    ];
+
+    // submit_random(seed, proof) is the first call of the outer outside execution
+    let seed = |nonce: u64| {
+        poseidon_hash_many(&[
+            Felt::from(nonce),
+            user_address.0,
+            consumer_address.0,
+            chain_id,
+        ])
+    };
+    let submitted_seed =
+        |result: &SignedOutsideExecution| get_calls(&result.outside_execution)[0].calldata[0];
+
+    results.sort_by_key(|result| submitted_seed(result) != seed(0));
+    assert_eq!(submitted_seed(&results[0]), seed(0));
+    assert_eq!(submitted_seed(&results[1]), seed(1));
+
+    // both bundles land when executed in order
+    let executor_account = sequencer.account(2);
+    for result in results {
+        let execute_result = executor_account
+            .execute_v3(vec![result.build_execute_from_outside_call().into()])
+            .send()
+            .await
+            .unwrap();
+
+        let receipt = TransactionWaiter::new(execute_result.transaction_hash, sequencer.provider())
+            .await
+            .unwrap();
+        assert_eq!(
+            receipt.receipt.execution_result(),
+            &ExecutionResult::Succeeded
+        );
+    }
+
+    // the confirmed nonces are released, the next bundle uses the stored nonce
+    let result = server
+        .post("/outside_execution")
+        .json(&requests[2])
+        .await
+        .json::<OutsideExecutionResult>()
+        .result;
+    assert_eq!(submitted_seed(&result), seed(2));
+}
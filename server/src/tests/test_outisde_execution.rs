@@ -1,14 +1,22 @@
 use crate::{
+    config::{Clock, SignatureValidation, DEFAULT_OUTSIDE_EXECUTION_EXPIRY},
+    routes::errors::ErrorBody,
     routes::outside_execution::{
-        build_signed_outside_execution_v2,
+        build_outside_execution_v2,
         context::RequestContext,
-        types::{OutsideExecution, OutsideExecutionV2, SignedOutsideExecution},
+        derive_outer_nonce,
+        types::{
+            get_window, Call, ExecutionWindow, OutsideExecution, OutsideExecutionV2,
+            SignedOutsideExecution,
+        },
         OutsideExecutionRequest, OutsideExecutionResult, ANY_CALLER,
     },
     tests::setup::{
-        declare_and_deploy, new_test_server, ACCOUNT_MOCK_ARTIFACT, ACCOUNT_MOCK_PRIVATE_KEY,
-        ACCOUNT_MOCK_PUBLIC_KEY, STRK_ADDRESS, VRF_ACCOUNT_ARTIFACT, VRF_ACCOUNT_PRIVATE_KEY,
-        VRF_ACCOUNT_PUBLIC_KEY, VRF_CONSUMER_ARTIFACT, VRF_PUBLIC_KEY, VRF_SECRET_KEY,
+        build_signed_outside_execution, build_signed_outside_execution_v2, declare_and_deploy,
+        deploy_vrf_account, katana_config, new_test_server, new_test_server_with_config,
+        ACCOUNT_MOCK_ARTIFACT, ACCOUNT_MOCK_PRIVATE_KEY, ACCOUNT_MOCK_PUBLIC_KEY, STRK_ADDRESS,
+        VRF_ACCOUNT_ARTIFACT, VRF_ACCOUNT_PRIVATE_KEY, VRF_ACCOUNT_PUBLIC_KEY,
+        VRF_CONSUMER_ARTIFACT, VRF_PUBLIC_KEY, VRF_SECRET_KEY,
     },
     Args,
 };
@@ -139,6 +147,7 @@ async fn test_outside_execution(sequencer: &RunnerCtx) {
         user_account_signer,
         chain_id,
         user_calls,
+        DEFAULT_OUTSIDE_EXECUTION_EXPIRY,
     )
     .await;
 
@@ -147,7 +156,8 @@ async fn test_outside_execution(sequencer: &RunnerCtx) {
     let args = Args::default()
         .with_account_address(&vrf_account_address.0.to_hex_string())
         .with_account_private_key(VRF_ACCOUNT_PRIVATE_KEY)
-        .with_secret_key(VRF_SECRET_KEY);
+        .with_secret_key(VRF_SECRET_KEY)
+        .with_chain(&format!("WP_KATANA={}", sequencer.url()));
 
     let server = new_test_server(&args).await;
 
@@ -156,6 +166,7 @@ async fn test_outside_execution(sequencer: &RunnerCtx) {
         context: RequestContext {
             chain_id: cairo_short_string_to_felt("WP_KATANA").unwrap(),
             rpc_url: Option::Some(sequencer.url()),
+            executor: None,
         },
     })
     .unwrap();
@@ -213,11 +224,15 @@ async fn test_outside_execution(sequencer: &RunnerCtx) {
 }
 
 pub fn mock_signed_outside_execution() -> SignedOutsideExecution {
+    mock_signed_outside_execution_with_calls(vec![])
+}
+
+pub fn mock_signed_outside_execution_with_calls(calls: Vec<Call>) -> SignedOutsideExecution {
     SignedOutsideExecution {
         address: felt!("0x123"),
         outside_execution: OutsideExecution::V2(OutsideExecutionV2 {
             caller: ANY_CALLER,
-            calls: vec![],
+            calls,
             execute_after: 0,
             execute_before: 0,
             nonce: felt!("0x0"),
@@ -241,6 +256,7 @@ async fn test_with_bad_chain_id() {
             chain_id: cairo_short_string_to_felt("WP_KATANA_THIS_IS_TOO_LONG_FOR_SHORT_STRING")
                 .unwrap(),
             rpc_url: Option::None,
+            executor: None,
         },
     })
     .unwrap();
@@ -250,7 +266,7 @@ async fn test_with_bad_chain_id() {
         .json(&signed_outisde_execution_request_json)
         .expect_failure()
         .await
-        .assert_status_not_found();
+        .assert_status_bad_request();
 
     let _ = server.get("/").expect_success();
 }
@@ -269,6 +285,7 @@ async fn test_with_bad_rpc_url() {
         context: RequestContext {
             chain_id: cairo_short_string_to_felt("WP_KATANA_").unwrap(),
             rpc_url: Option::Some(Url::parse("not_a_rpc_url").unwrap()),
+            executor: None,
         },
     })
     .unwrap();
@@ -278,7 +295,7 @@ async fn test_with_bad_rpc_url() {
         .json(&signed_outisde_execution_request_json)
         .expect_failure()
         .await
-        .assert_status_not_found();
+        .assert_status_bad_request();
 
     let _ = server.get("/").expect_success();
 }
@@ -296,12 +313,14 @@ async fn test_with_malformed_json() {
         "context": "invalid_json",
     });
 
-    server
+    let response = server
         .post("/outside_execution")
         .json(&signed_outisde_execution_request_json)
         .expect_failure()
-        .await
-        .assert_status_unprocessable_entity();
+        .await;
+
+    response.assert_status_unprocessable_entity();
+    assert_eq!(response.json::<ErrorBody>().code, "invalid_json");
 
     let _ = server.get("/").expect_success();
 }
@@ -353,3 +372,499 @@ async fn test_with_invalid_json_value() {
 
     let _ = server.get("/").expect_success();
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_with_unknown_chain() {
+    let args = Args::default()
+        .with_account_private_key(VRF_ACCOUNT_PRIVATE_KEY)
+        .with_secret_key(VRF_SECRET_KEY);
+
+    let server = new_test_server(&args).await;
+
+    let signed_outisde_execution_request_json = serde_json::to_value(&OutsideExecutionRequest {
+        request: mock_signed_outside_execution(),
+        context: RequestContext {
+            chain_id: cairo_short_string_to_felt("WP_UNKNOWN").unwrap(),
+            rpc_url: Option::Some(Url::parse("http://localhost:5050").unwrap()),
+            executor: None,
+        },
+    })
+    .unwrap();
+
+    let response = server
+        .post("/outside_execution")
+        .json(&signed_outisde_execution_request_json)
+        .expect_failure()
+        .await;
+
+    response.assert_status_bad_request();
+    response.assert_json(&json!({
+        "code": "unknown_chain",
+        "message": "Unknown chain: WP_UNKNOWN",
+        "details": { "chain_id": "WP_UNKNOWN" },
+    }));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_with_rejected_rpc_url() {
+    let args = Args::default()
+        .with_account_private_key(VRF_ACCOUNT_PRIVATE_KEY)
+        .with_secret_key(VRF_SECRET_KEY)
+        .with_chain("WP_KATANA=http://localhost:5050");
+
+    let server = new_test_server(&args).await;
+
+    let signed_outisde_execution_request_json = serde_json::to_value(&OutsideExecutionRequest {
+        request: mock_signed_outside_execution(),
+        context: RequestContext {
+            chain_id: cairo_short_string_to_felt("WP_KATANA").unwrap(),
+            rpc_url: Option::Some(Url::parse("http://169.254.169.254/latest").unwrap()),
+            executor: None,
+        },
+    })
+    .unwrap();
+
+    server
+        .post("/outside_execution")
+        .json(&signed_outisde_execution_request_json)
+        .expect_failure()
+        .await
+        .assert_status_forbidden();
+}
+
+fn mock_request_random_call(vrf_account_address: Felt, caller: Felt) -> Call {
+    Call {
+        to: vrf_account_address.into(),
+        selector: selector!("request_random"),
+        calldata: vec![
+            caller,
+            felt!("0x0"),   // Source::Nonce
+            felt!("0x222"), // address
+        ],
+    }
+}
+
+fn mock_dice_call(consumer_address: Felt) -> Call {
+    Call {
+        to: consumer_address.into(),
+        selector: selector!("dice"),
+        calldata: vec![],
+    }
+}
+
+async fn post_katana_outside_execution(calls: Vec<Call>) -> axum_test::TestResponse {
+    let args = Args::default()
+        .with_account_address("0x123")
+        .with_account_private_key(VRF_ACCOUNT_PRIVATE_KEY)
+        .with_secret_key(VRF_SECRET_KEY)
+        .with_chain("WP_KATANA=http://localhost:5050");
+
+    let server = new_test_server(&args).await;
+
+    let signed_outisde_execution_request_json = serde_json::to_value(&OutsideExecutionRequest {
+        request: mock_signed_outside_execution_with_calls(calls),
+        context: RequestContext {
+            chain_id: cairo_short_string_to_felt("WP_KATANA").unwrap(),
+            rpc_url: Option::None,
+            executor: None,
+        },
+    })
+    .unwrap();
+
+    server
+        .post("/outside_execution")
+        .json(&signed_outisde_execution_request_json)
+        .expect_failure()
+        .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_with_request_random_to_other_contract() {
+    let response = post_katana_outside_execution(vec![
+        mock_request_random_call(felt!("0x888"), felt!("0x111")),
+        mock_dice_call(felt!("0x111")),
+    ])
+    .await;
+
+    response.assert_status_unprocessable_entity();
+    assert_eq!(
+        response.json::<ErrorBody>().code,
+        "request_random_target_mismatch"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_with_request_random_caller_not_called() {
+    let response = post_katana_outside_execution(vec![
+        mock_request_random_call(felt!("0x123"), felt!("0x111")),
+        mock_dice_call(felt!("0x999")),
+    ])
+    .await;
+
+    response.assert_status_unprocessable_entity();
+    assert_eq!(
+        response.json::<ErrorBody>().code,
+        "request_random_caller_mismatch"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_with_request_random_caller_called_before() {
+    let response = post_katana_outside_execution(vec![
+        mock_dice_call(felt!("0x111")),
+        mock_request_random_call(felt!("0x123"), felt!("0x111")),
+        mock_dice_call(felt!("0x999")),
+    ])
+    .await;
+
+    response.assert_status_unprocessable_entity();
+    assert_eq!(
+        response.json::<ErrorBody>().code,
+        "request_random_caller_mismatch"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_with_no_call_after_request_random() {
+    let response = post_katana_outside_execution(vec![mock_request_random_call(
+        felt!("0x123"),
+        felt!("0x111"),
+    )])
+    .await;
+
+    response.assert_status_unprocessable_entity();
+    response.assert_json(&json!({
+        "code": "no_call_after_request_random",
+        "message": "No call after request_random",
+    }));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[katana_runner::test(accounts = 10, chain_id = Felt::from_hex_unchecked("0x57505f4b4154414e41"))] // WP_KATANA
+async fn test_with_invalid_signature(sequencer: &RunnerCtx) {
+    let chain_id = sequencer.provider().chain_id().await.unwrap();
+
+    let (user_account_address, _) = declare_and_deploy(
+        sequencer,
+        ACCOUNT_MOCK_ARTIFACT,
+        vec![ACCOUNT_MOCK_PUBLIC_KEY],
+    )
+    .await;
+
+    // signed by another key than the user account's
+    let signed_outside_execution = build_signed_outside_execution_v2(
+        user_account_address.0,
+        LocalWallet::from_signing_key(SigningKey::from_secret_scalar(felt!("0x999"))),
+        chain_id,
+        vec![
+            mock_request_random_call(felt!("0x123"), felt!("0x111")),
+            mock_dice_call(felt!("0x111")),
+        ],
+        DEFAULT_OUTSIDE_EXECUTION_EXPIRY,
+    )
+    .await;
+
+    let args = Args::default()
+        .with_account_address("0x123")
+        .with_account_private_key(VRF_ACCOUNT_PRIVATE_KEY)
+        .with_secret_key(VRF_SECRET_KEY)
+        .with_chain(&format!("WP_KATANA={}", sequencer.url()))
+        .with_signature_validation(SignatureValidation::Rpc);
+
+    let server = new_test_server(&args).await;
+
+    let signed_outisde_execution_request_json = serde_json::to_value(&OutsideExecutionRequest {
+        request: signed_outside_execution,
+        context: RequestContext {
+            chain_id: cairo_short_string_to_felt("WP_KATANA").unwrap(),
+            rpc_url: Option::None,
+            executor: None,
+        },
+    })
+    .unwrap();
+
+    let response = server
+        .post("/outside_execution")
+        .json(&signed_outisde_execution_request_json)
+        .expect_failure()
+        .await;
+
+    response.assert_status_unprocessable_entity();
+    assert_eq!(response.json::<ErrorBody>().code, "invalid_signature");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[katana_runner::test(accounts = 10, chain_id = Felt::from_hex_unchecked("0x57505f4b4154414e41"))] // WP_KATANA
+async fn test_outer_window_follows_inner_window(sequencer: &RunnerCtx) {
+    let chain_id = sequencer.provider().chain_id().await.unwrap();
+    let vrf_account_address = deploy_vrf_account(sequencer).await;
+
+    let (user_account_address, _) = declare_and_deploy(
+        sequencer,
+        ACCOUNT_MOCK_ARTIFACT,
+        vec![ACCOUNT_MOCK_PUBLIC_KEY],
+    )
+    .await;
+    let user_signer =
+        LocalWallet::from_signing_key(SigningKey::from_secret_scalar(ACCOUNT_MOCK_PRIVATE_KEY));
+    let user_calls = vec![
+        mock_request_random_call(vrf_account_address.0, felt!("0x111")),
+        mock_dice_call(felt!("0x111")),
+    ];
+
+    let mut config = katana_config(sequencer, vrf_account_address.0, VRF_SECRET_KEY);
+    config.outside_execution.clock = Clock::Block;
+    let server = new_test_server_with_config(config).await;
+
+    let post = |request: SignedOutsideExecution| {
+        server
+            .post("/outside_execution")
+            .json(&OutsideExecutionRequest {
+                request,
+                context: RequestContext {
+                    chain_id,
+                    rpc_url: None,
+                    executor: None,
+                },
+            })
+    };
+
+    // the outer outside execution expires with the user's one
+    let inner = build_signed_outside_execution_v2(
+        user_account_address.0,
+        user_signer.clone(),
+        chain_id,
+        user_calls.clone(),
+        60,
+    )
+    .await;
+    let inner_window = get_window(&inner.outside_execution);
+
+    let result = post(inner).await.json::<OutsideExecutionResult>().result;
+    assert_eq!(get_window(&result.outside_execution), inner_window);
+
+    // an expired user outside execution is rejected before proving
+    let expired = build_signed_outside_execution(
+        user_account_address.0,
+        user_signer,
+        chain_id,
+        build_outside_execution_v2(
+            user_calls,
+            ExecutionWindow {
+                execute_after: 0,
+                execute_before: 1,
+            },
+            ANY_CALLER,
+            felt!("0x1"),
+        ),
+    )
+    .await;
+
+    let response = post(expired).expect_failure().await;
+    response.assert_status_unprocessable_entity();
+    assert_eq!(
+        response.json::<ErrorBody>().code,
+        "outside_execution_expired"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[katana_runner::test(accounts = 10, chain_id = Felt::from_hex_unchecked("0x57505f4b4154414e41"))] // WP_KATANA
+async fn test_outside_execution_restricted_to_executor(sequencer: &RunnerCtx) {
+    let chain_id = sequencer.provider().chain_id().await.unwrap();
+    let vrf_account_address = deploy_vrf_account(sequencer).await;
+
+    let (consumer_address, _) = declare_and_deploy(
+        sequencer,
+        VRF_CONSUMER_ARTIFACT,
+        vec![vrf_account_address.0],
+    )
+    .await;
+    let (user_account_address, _) = declare_and_deploy(
+        sequencer,
+        ACCOUNT_MOCK_ARTIFACT,
+        vec![ACCOUNT_MOCK_PUBLIC_KEY],
+    )
+    .await;
+    let user_signer =
+        LocalWallet::from_signing_key(SigningKey::from_secret_scalar(ACCOUNT_MOCK_PRIVATE_KEY));
+
+    let executor_account = sequencer.account(2);
+    let other_account = sequencer.account(3);
+
+    let mut config = katana_config(sequencer, vrf_account_address.0, VRF_SECRET_KEY);
+    for chain in config.chains.values_mut() {
+        chain.allowed_executors = vec![executor_account.address()];
+    }
+    let server = new_test_server_with_config(config).await;
+
+    let signed_outside_execution = build_signed_outside_execution_v2(
+        user_account_address.0,
+        user_signer,
+        chain_id,
+        vec![
+            Call {
+                to: vrf_account_address,
+                selector: selector!("request_random"),
+                calldata: vec![
+                    consumer_address.0,
+                    felt!("0x0"), // Source::Nonce
+                    user_account_address.0,
+                ],
+            },
+            mock_dice_call(consumer_address.0),
+        ],
+        DEFAULT_OUTSIDE_EXECUTION_EXPIRY,
+    )
+    .await;
+    let request = |executor: Felt| OutsideExecutionRequest {
+        request: signed_outside_execution.clone(),
+        context: RequestContext {
+            chain_id,
+            rpc_url: None,
+            executor: Some(executor),
+        },
+    };
+
+    // executors must be allowed for the chain
+    let response = server
+        .post("/outside_execution")
+        .json(&request(other_account.address()))
+        .expect_failure()
+        .await;
+    response.assert_status_forbidden();
+    assert_eq!(response.json::<ErrorBody>().code, "executor_rejected");
+
+    let result = server
+        .post("/outside_execution")
+        .json(&request(executor_account.address()))
+        .await
+        .json::<OutsideExecutionResult>()
+        .result;
+    match &result.outside_execution {
+        OutsideExecution::V2(v2) => assert_eq!(v2.caller.0, executor_account.address()),
+        OutsideExecution::V3(_) => panic!("expected a V2 outside execution"),
+    }
+
+    // only the executor can submit the bundle
+    let execution_call = result.build_execute_from_outside_call();
+    assert!(other_account
+        .execute_v3(vec![execution_call.clone().into()])
+        .send()
+        .await
+        .is_err());
+
+    let execute_result = executor_account
+        .execute_v3(vec![execution_call.into()])
+        .send()
+        .await
+        .unwrap();
+    TransactionWaiter::new(execute_result.transaction_hash, sequencer.provider())
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[katana_runner::test(accounts = 10, chain_id = Felt::from_hex_unchecked("0x57505f4b4154414e41"))] // WP_KATANA
+async fn test_retries_return_the_same_bundle(sequencer: &RunnerCtx) {
+    let chain_id = sequencer.provider().chain_id().await.unwrap();
+    let vrf_account_address = deploy_vrf_account(sequencer).await;
+
+    let (consumer_address, _) = declare_and_deploy(
+        sequencer,
+        VRF_CONSUMER_ARTIFACT,
+        vec![vrf_account_address.0],
+    )
+    .await;
+    let (user_account_address, _) = declare_and_deploy(
+        sequencer,
+        ACCOUNT_MOCK_ARTIFACT,
+        vec![ACCOUNT_MOCK_PUBLIC_KEY],
+    )
+    .await;
+    let user_signer =
+        LocalWallet::from_signing_key(SigningKey::from_secret_scalar(ACCOUNT_MOCK_PRIVATE_KEY));
+
+    let signed_outside_execution = build_signed_outside_execution_v2(
+        user_account_address.0,
+        user_signer,
+        chain_id,
+        vec![
+            Call {
+                to: vrf_account_address,
+                selector: selector!("request_random"),
+                calldata: vec![
+                    consumer_address.0,
+                    felt!("0x0"), // Source::Nonce
+                    user_account_address.0,
+                ],
+            },
+            mock_dice_call(consumer_address.0),
+        ],
+        DEFAULT_OUTSIDE_EXECUTION_EXPIRY,
+    )
+    .await;
+    let request = OutsideExecutionRequest {
+        request: signed_outside_execution,
+        context: RequestContext {
+            chain_id,
+            rpc_url: None,
+            executor: None,
+        },
+    };
+
+    let config = katana_config(sequencer, vrf_account_address.0, VRF_SECRET_KEY);
+    let server = new_test_server_with_config(config).await;
+
+    let post = |idempotency_key: Option<&str>| {
+        let mut post = server.post("/outside_execution").json(&request);
+        if let Some(idempotency_key) = idempotency_key {
+            post = post.add_header("idempotency-key", idempotency_key);
+        }
+        post
+    };
+    let outer_nonce = |result: &SignedOutsideExecution| match &result.outside_execution {
+        OutsideExecution::V2(v2) => v2.nonce,
+        OutsideExecution::V3(_) => panic!("expected a V2 outside execution"),
+    };
+
+    // retries, concurrent or not, get the same bundle
+    let (first, second) = tokio::join!(post(None), post(None));
+    let first = first.json::<serde_json::Value>();
+    assert_eq!(first, second.json::<serde_json::Value>());
+    assert_eq!(first, post(None).await.json::<serde_json::Value>());
+
+    let bundle = serde_json::from_value::<OutsideExecutionResult>(first)
+        .unwrap()
+        .result;
+    assert_eq!(
+        outer_nonce(&bundle),
+        derive_outer_nonce(chain_id, &request.request)
+    );
+
+    // an idempotency key does not build another bundle
+    let keyed = post(Some("retry-1")).await.json::<OutsideExecutionResult>();
+    assert_eq!(
+        serde_json::to_value(&keyed.result).unwrap(),
+        serde_json::to_value(&bundle).unwrap()
+    );
+
+    // a bundle executes once
+    let executor_account = sequencer.account(2);
+    let execution_call = bundle.build_execute_from_outside_call();
+
+    let execute_result = executor_account
+        .execute_v3(vec![execution_call.clone().into()])
+        .send()
+        .await
+        .unwrap();
+    TransactionWaiter::new(execute_result.transaction_hash, sequencer.provider())
+        .await
+        .unwrap();
+
+    assert!(executor_account
+        .execute_v3(vec![execution_call.into()])
+        .send()
+        .await
+        .is_err());
+}
@@ -0,0 +1,174 @@
+use crate::{
+    config::Config,
+    routes::{
+        errors::ErrorBody,
+        info::InfoResult,
+        proof::{JsonResult, ProofBatchResult},
+        verify::VerifyResult,
+    },
+    tests::setup::{new_test_server, new_test_server_with_config, VRF_SECRET_KEY},
+    Args,
+};
+use serde_json::json;
+
+#[tokio::test]
+async fn test_proof_with_hex_and_decimal_seed() {
+    let args = Args::default().with_secret_key(VRF_SECRET_KEY);
+    let server = new_test_server(&args).await;
+
+    let hex = server
+        .post("/proof")
+        .json(&json!({ "seed": ["0x1a4", "0x10"] }))
+        .await
+        .json::<JsonResult>();
+
+    let decimal = server
+        .post("/proof")
+        .json(&json!({ "seed": ["420", "16"] }))
+        .await
+        .json::<JsonResult>();
+
+    assert_eq!(hex.result, decimal.result);
+}
+
+#[tokio::test]
+async fn test_proof_with_invalid_seed() {
+    let args = Args::default().with_secret_key(VRF_SECRET_KEY);
+    let server = new_test_server(&args).await;
+
+    for (seed, message) in [
+        (json!([]), "Invalid seed: seed must not be empty"),
+        (json!(["0x1", ""]), "Invalid seed: seed[1] ``"),
+        (json!(["0x"]), "Invalid seed: seed[0] `0x`"),
+        (json!(["0x1", "0xzz"]), "Invalid seed: seed[1] `0xzz`"),
+        (
+            json!(["0x800000000000011000000000000000000000000000000000000000000000001"]),
+            "Invalid seed: seed[0]",
+        ),
+    ] {
+        let response = server
+            .post("/proof")
+            .json(&json!({ "seed": seed }))
+            .expect_failure()
+            .await;
+
+        response.assert_status_bad_request();
+
+        let error = response.json::<ErrorBody>();
+        assert_eq!(error.code, "invalid_seed");
+        assert!(
+            error.message.starts_with(message),
+            "unexpected message for {seed}: {}",
+            error.message
+        );
+    }
+
+    let response = server
+        .post("/proof")
+        .json(&json!({ "seed": "0x1" }))
+        .expect_failure()
+        .await;
+
+    response.assert_status_unprocessable_entity();
+    assert_eq!(response.json::<ErrorBody>().code, "invalid_json");
+
+    let _ = server.get("/").expect_success();
+}
+
+#[tokio::test]
+async fn test_proof_batch() {
+    let args = Args::default().with_secret_key(VRF_SECRET_KEY);
+    let server = new_test_server(&args).await;
+
+    let single = server
+        .post("/proof")
+        .json(&json!({ "seed": ["0x2", "0x3"] }))
+        .await
+        .json::<JsonResult>();
+
+    let batch = server
+        .post("/proof/batch")
+        .json(&json!({ "seeds": [["0x1"], ["0x2", "0x3"], ["0xzz"], []] }))
+        .await
+        .json::<ProofBatchResult>();
+
+    assert_eq!(batch.results.len(), 4);
+    assert!(batch.results[0].result.is_some());
+    assert_eq!(batch.results[1].result.as_ref(), Some(&single.result));
+    assert_ne!(batch.results[0].result, batch.results[1].result);
+
+    let error = batch.results[2].error.as_ref().unwrap();
+    assert_eq!(error.code, "invalid_seed");
+    assert!(error.message.contains("seed[0] `0xzz`"));
+    assert!(batch.results[2].result.is_none());
+
+    assert_eq!(
+        batch.results[3].error.as_ref().unwrap().message,
+        "Invalid seed: seed must not be empty"
+    );
+}
+
+#[tokio::test]
+async fn test_proof_batch_too_large() {
+    let mut config = Config::load(&Args::default().with_secret_key(VRF_SECRET_KEY)).unwrap();
+    config.limits.max_proof_batch_size = 2;
+    let server = new_test_server_with_config(config).await;
+
+    let response = server
+        .post("/proof/batch")
+        .json(&json!({ "seeds": [["0x1"], ["0x2"], ["0x3"]] }))
+        .expect_failure()
+        .await;
+
+    response.assert_status_bad_request();
+    assert_eq!(response.json::<ErrorBody>().code, "batch_too_large");
+}
+
+#[tokio::test]
+async fn test_verify_proof() {
+    let args = Args::default().with_secret_key(VRF_SECRET_KEY);
+    let server = new_test_server(&args).await;
+
+    let proof = server
+        .post("/proof")
+        .json(&json!({ "seed": ["0x1a4"] }))
+        .await
+        .json::<JsonResult>()
+        .result;
+    let info = server.get("/info").await.json::<InfoResult>();
+
+    let verified = server
+        .post("/verify")
+        .json(&json!({
+            "seed": ["0x1a4"],
+            "proof": proof,
+            "public_key_x": info.public_key_x,
+            "public_key_y": info.public_key_y,
+        }))
+        .await
+        .json::<VerifyResult>();
+    assert_eq!(verified.rnd, proof.rnd);
+
+    let response = server
+        .post("/verify")
+        .json(&json!({ "seed": ["0x1a5"], "proof": proof }))
+        .expect_failure()
+        .await;
+
+    response.assert_status_unprocessable_entity();
+    assert_eq!(response.json::<ErrorBody>().code, "invalid_proof");
+
+    let response = server
+        .post("/verify")
+        .json(&json!({
+            "seed": ["0x1a4"],
+            "proof": proof,
+            "public_key_x": "0xzz",
+            "public_key_y": info.public_key_y,
+        }))
+        .expect_failure()
+        .await;
+
+    response.assert_status_bad_request();
+    assert_eq!(response.json::<ErrorBody>().code, "malformed_proof");
+}
@@ -0,0 +1,70 @@
+use crate::{
+    state::{vrf_account::check_vrf_public_keys, AppState},
+    tests::setup::{
+        declare_and_deploy, deploy_vrf_account, katana_config, VRF_PROVIDER_ARTIFACT,
+        VRF_PUBLIC_KEY, VRF_SECRET_KEY,
+    },
+};
+use katana_runner::RunnerCtx;
+use starknet::accounts::Account;
+use starknet::core::types::Felt;
+use std::time::Duration;
+use url::Url;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[tokio::test(flavor = "multi_thread")]
+#[katana_runner::test(accounts = 10, chain_id = Felt::from_hex_unchecked("0x57505f4b4154414e41"))] // WP_KATANA
+async fn test_public_key_check(sequencer: &RunnerCtx) {
+    let vrf_account_address = deploy_vrf_account(sequencer).await;
+
+    let config = katana_config(sequencer, vrf_account_address.0, VRF_SECRET_KEY);
+    let app_state = AppState::from_config(config).await.unwrap();
+    assert!(check_vrf_public_keys(&app_state.chains, TIMEOUT)
+        .await
+        .is_ok());
+
+    let config = katana_config(sequencer, vrf_account_address.0, Felt::from(421_u64));
+    let app_state = AppState::from_config(config).await.unwrap();
+    let error = check_vrf_public_keys(&app_state.chains, TIMEOUT)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("VRF public key mismatch"));
+
+    // chains whose RPC can't be reached fail the check
+    let mut config = katana_config(sequencer, vrf_account_address.0, VRF_SECRET_KEY);
+    for chain in config.chains.values_mut() {
+        chain.rpc_url = Some(Url::parse("http://127.0.0.1:1").unwrap());
+    }
+    let app_state = AppState::from_config(config).await.unwrap();
+    let error = check_vrf_public_keys(&app_state.chains, TIMEOUT)
+        .await
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("could not check the VRF public key"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[katana_runner::test(accounts = 10, chain_id = Felt::from_hex_unchecked("0x57505f4b4154414e41"))] // WP_KATANA
+async fn test_public_key_check_with_legacy_provider(sequencer: &RunnerCtx) {
+    let owner = sequencer.account(0).address();
+    let (vrf_provider_address, _) = declare_and_deploy(
+        sequencer,
+        VRF_PROVIDER_ARTIFACT,
+        vec![owner, VRF_PUBLIC_KEY[0], VRF_PUBLIC_KEY[1]],
+    )
+    .await;
+
+    let config = katana_config(sequencer, vrf_provider_address.0, VRF_SECRET_KEY);
+    let app_state = AppState::from_config(config).await.unwrap();
+    assert!(check_vrf_public_keys(&app_state.chains, TIMEOUT)
+        .await
+        .is_ok());
+
+    let config = katana_config(sequencer, vrf_provider_address.0, Felt::from(421_u64));
+    let app_state = AppState::from_config(config).await.unwrap();
+    assert!(check_vrf_public_keys(&app_state.chains, TIMEOUT)
+        .await
+        .is_err());
+}
@@ -0,0 +1,53 @@
+use crate::{
+    routes::ready::{CheckStatus, ReadyResult, FEE_TOKEN_BALANCE_CHECK},
+    tests::setup::{
+        deploy_vrf_account, katana_config, new_test_server_with_config, VRF_SECRET_KEY,
+    },
+};
+use katana_runner::RunnerCtx;
+use starknet::core::types::Felt;
+
+#[tokio::test(flavor = "multi_thread")]
+#[katana_runner::test(accounts = 10, chain_id = Felt::from_hex_unchecked("0x57505f4b4154414e41"))] // WP_KATANA
+async fn test_ready(sequencer: &RunnerCtx) {
+    let vrf_account_address = deploy_vrf_account(sequencer).await;
+    let mut config = katana_config(sequencer, vrf_account_address.0, VRF_SECRET_KEY);
+
+    let server = new_test_server_with_config(config.clone()).await;
+    let result = server.get("/ready").await.json::<ReadyResult>();
+
+    assert!(result.ready);
+    assert_eq!(result.chains.len(), 1);
+    assert_eq!(result.chains[0].chain_id, "WP_KATANA");
+    assert!(result.chains[0]
+        .checks
+        .iter()
+        .all(|check| check.status == CheckStatus::Ok));
+
+    // a low balance is reported without draining the instance
+    config.readiness.min_fee_token_balance = u128::MAX;
+
+    let server = new_test_server_with_config(config).await;
+    let result = server.get("/ready").await.json::<ReadyResult>();
+
+    assert!(result.ready);
+    let balance = result.chains[0]
+        .checks
+        .iter()
+        .find(|check| check.name == FEE_TOKEN_BALANCE_CHECK)
+        .unwrap();
+    assert_eq!(balance.status, CheckStatus::Failed);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[katana_runner::test(accounts = 10, chain_id = Felt::from_hex_unchecked("0x57505f4b4154414e41"))] // WP_KATANA
+async fn test_ready_with_wrong_public_key(sequencer: &RunnerCtx) {
+    let vrf_account_address = deploy_vrf_account(sequencer).await;
+    let config = katana_config(sequencer, vrf_account_address.0, Felt::from(421_u64));
+
+    let server = new_test_server_with_config(config).await;
+    let response = server.get("/ready").expect_failure().await;
+
+    response.assert_status_service_unavailable();
+    assert!(!response.json::<ReadyResult>().ready);
+}
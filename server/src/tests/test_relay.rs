@@ -0,0 +1,223 @@
+use crate::{
+    config::{RelayerConfig, DEFAULT_OUTSIDE_EXECUTION_EXPIRY},
+    routes::{
+        errors::ErrorBody,
+        outside_execution::{
+            context::RequestContext, relay::RelayResult, types::OutsideExecution,
+            OutsideExecutionRequest,
+        },
+    },
+    state::secrets::SecretOptions,
+    tests::setup::{
+        build_signed_outside_execution_v2, declare_and_deploy, deploy_vrf_account, katana_config,
+        new_test_server_with_config, ACCOUNT_MOCK_ARTIFACT, ACCOUNT_MOCK_PRIVATE_KEY,
+        ACCOUNT_MOCK_PUBLIC_KEY, VRF_ACCOUNT_PRIVATE_KEY, VRF_CONSUMER_ARTIFACT, VRF_SECRET_KEY,
+    },
+};
+use dojo_utils::TransactionWaiter;
+use katana_runner::RunnerCtx;
+use starknet::{
+    core::{
+        types::{Call, ExecutionResult},
+        utils::cairo_short_string_to_felt,
+    },
+    macros::selector,
+    providers::Provider,
+    signers::{LocalWallet, SigningKey},
+};
+use starknet_crypto::Felt;
+
+#[tokio::test(flavor = "multi_thread")]
+#[katana_runner::test(accounts = 10, chain_id = Felt::from_hex_unchecked("0x57505f4b4154414e41"))] // WP_KATANA
+async fn test_relayed_bundles_land(sequencer: &RunnerCtx) {
+    let chain_id = sequencer.provider().chain_id().await.unwrap();
+    let vrf_account_address = deploy_vrf_account(sequencer).await;
+
+    let (consumer_address, _) = declare_and_deploy(
+        sequencer,
+        VRF_CONSUMER_ARTIFACT,
+        vec![vrf_account_address.0],
+    )
+    .await;
+    let (user_address, _) = declare_and_deploy(
+        sequencer,
+        ACCOUNT_MOCK_ARTIFACT,
+        vec![ACCOUNT_MOCK_PUBLIC_KEY],
+    )
+    .await;
+    let user_signer =
+        LocalWallet::from_signing_key(SigningKey::from_secret_scalar(ACCOUNT_MOCK_PRIVATE_KEY));
+
+    let user_calls = vec![
+        Call {
+            to: vrf_account_address.0,
+            selector: selector!("request_random"),
+            calldata: vec![
+                consumer_address.0,
+                Felt::ONE, // Source::Salt
+                cairo_short_string_to_felt("salt").unwrap(),
+            ],
+        }
+        .into(),
+        Call {
+            to: consumer_address.0,
+            selector: selector!("dice_with_salt"),
+            calldata: vec![],
+        }
+        .into(),
+    ];
+
+    let mut requests = vec![];
+    for _ in 0..2 {
+        let request = build_signed_outside_execution_v2(
+            user_address.0,
+            user_signer.clone(),
+            chain_id,
+            user_calls.clone(),
+            DEFAULT_OUTSIDE_EXECUTION_EXPIRY,
+        )
+        .await;
+
+        requests.push(OutsideExecutionRequest {
+            request,
+            context: RequestContext {
+                chain_id,
+                rpc_url: Some(sequencer.url()),
+                executor: None,
+            },
+        });
+    }
+
+    // the VRF account is funded and can relay its own bundles
+    let mut config = katana_config(sequencer, vrf_account_address.0, VRF_SECRET_KEY);
+    for chain in config.chains.values_mut() {
+        chain.relayers = vec![RelayerConfig {
+            address: vrf_account_address.0,
+            private_key: SecretOptions {
+                value: Some(VRF_ACCOUNT_PRIVATE_KEY.to_string()),
+                ..Default::default()
+            },
+        }];
+    }
+    let server = new_test_server_with_config(config).await;
+
+    // concurrent requests get consecutive relayer nonces
+    let (first, second) = tokio::join!(
+        server.post("/outside_execution/relay").json(&requests[0]),
+        server.post("/outside_execution/relay").json(&requests[1]),
+    );
+    let first = first.json::<RelayResult>();
+    let second = second.json::<RelayResult>();
+    let OutsideExecution::V2(outer) = &first.bundle.result.outside_execution else {
+        panic!("expected a V2 outside execution");
+    };
+    assert_eq!(outer.caller, vrf_account_address);
+
+    for result in [&first, &second] {
+        let receipt = TransactionWaiter::new(result.transaction_hash, sequencer.provider())
+            .await
+            .unwrap();
+        assert_eq!(
+            receipt.receipt.execution_result(),
+            &ExecutionResult::Succeeded
+        );
+    }
+
+    // retries get the transaction submitted for the first request
+    let retry = server
+        .post("/outside_execution/relay")
+        .json(&requests[0])
+        .await
+        .json::<RelayResult>();
+    assert_eq!(retry.transaction_hash, first.transaction_hash);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[katana_runner::test(accounts = 10, chain_id = Felt::from_hex_unchecked("0x57505f4b4154414e41"))] // WP_KATANA
+async fn test_relay_is_refused(sequencer: &RunnerCtx) {
+    let chain_id = sequencer.provider().chain_id().await.unwrap();
+    let vrf_account_address = deploy_vrf_account(sequencer).await;
+
+    let (consumer_address, _) = declare_and_deploy(
+        sequencer,
+        VRF_CONSUMER_ARTIFACT,
+        vec![vrf_account_address.0],
+    )
+    .await;
+    let (user_address, _) = declare_and_deploy(
+        sequencer,
+        ACCOUNT_MOCK_ARTIFACT,
+        vec![ACCOUNT_MOCK_PUBLIC_KEY],
+    )
+    .await;
+    let user_signer =
+        LocalWallet::from_signing_key(SigningKey::from_secret_scalar(ACCOUNT_MOCK_PRIVATE_KEY));
+
+    let request = build_signed_outside_execution_v2(
+        user_address.0,
+        user_signer,
+        chain_id,
+        vec![
+            Call {
+                to: vrf_account_address.0,
+                selector: selector!("request_random"),
+                calldata: vec![
+                    consumer_address.0,
+                    Felt::ZERO, // Source::Nonce
+                    user_address.0,
+                ],
+            }
+            .into(),
+            Call {
+                to: consumer_address.0,
+                selector: selector!("dice"),
+                calldata: vec![],
+            }
+            .into(),
+        ],
+        DEFAULT_OUTSIDE_EXECUTION_EXPIRY,
+    )
+    .await;
+
+    let request = OutsideExecutionRequest {
+        request,
+        context: RequestContext {
+            chain_id,
+            rpc_url: Some(sequencer.url()),
+            executor: None,
+        },
+    };
+
+    // chains without relayers
+    let mut config = katana_config(sequencer, vrf_account_address.0, VRF_SECRET_KEY);
+    let server = new_test_server_with_config(config.clone()).await;
+
+    let response = server
+        .post("/outside_execution/relay")
+        .json(&request)
+        .expect_failure()
+        .await;
+    response.assert_status_service_unavailable();
+    assert_eq!(response.json::<ErrorBody>().code, "relayer_unavailable");
+
+    // transactions estimated above the relayer max fee
+    for chain in config.chains.values_mut() {
+        chain.relayers = vec![RelayerConfig {
+            address: vrf_account_address.0,
+            private_key: SecretOptions {
+                value: Some(VRF_ACCOUNT_PRIVATE_KEY.to_string()),
+                ..Default::default()
+            },
+        }];
+        chain.relayer_max_fee = 1;
+    }
+    let server = new_test_server_with_config(config).await;
+
+    let response = server
+        .post("/outside_execution/relay")
+        .json(&request)
+        .expect_failure()
+        .await;
+    response.assert_status_unprocessable_entity();
+    assert_eq!(response.json::<ErrorBody>().code, "relay_fee_too_high");
+}
@@ -0,0 +1,158 @@
+use crate::{
+    config::{RelayerConfig, DEFAULT_OUTSIDE_EXECUTION_EXPIRY},
+    routes::{
+        errors::ErrorBody,
+        outside_execution::{
+            context::RequestContext, relay::RelayResult, types::OutsideExecution,
+            OutsideExecutionRequest, OutsideExecutionResult,
+        },
+    },
+    state::{
+        secrets::SecretOptions,
+        tracker::{BundleStatus, TrackedBundle},
+    },
+    tests::setup::{
+        build_signed_outside_execution_v2, declare_and_deploy, deploy_vrf_account, katana_config,
+        new_test_server_with_config, ACCOUNT_MOCK_ARTIFACT, ACCOUNT_MOCK_PRIVATE_KEY,
+        ACCOUNT_MOCK_PUBLIC_KEY, VRF_ACCOUNT_PRIVATE_KEY, VRF_CONSUMER_ARTIFACT, VRF_SECRET_KEY,
+    },
+};
+use dojo_utils::TransactionWaiter;
+use katana_runner::RunnerCtx;
+use starknet::{
+    accounts::Account,
+    core::{types::Call, utils::cairo_short_string_to_felt},
+    macros::selector,
+    providers::Provider,
+    signers::{LocalWallet, SigningKey},
+};
+use starknet_crypto::Felt;
+
+#[tokio::test(flavor = "multi_thread")]
+#[katana_runner::test(accounts = 10, chain_id = Felt::from_hex_unchecked("0x57505f4b4154414e41"))] // WP_KATANA
+async fn test_status_of_returned_and_relayed_bundles(sequencer: &RunnerCtx) {
+    let chain_id = sequencer.provider().chain_id().await.unwrap();
+    let vrf_account_address = deploy_vrf_account(sequencer).await;
+
+    let (consumer_address, _) = declare_and_deploy(
+        sequencer,
+        VRF_CONSUMER_ARTIFACT,
+        vec![vrf_account_address.0],
+    )
+    .await;
+    let (user_address, _) = declare_and_deploy(
+        sequencer,
+        ACCOUNT_MOCK_ARTIFACT,
+        vec![ACCOUNT_MOCK_PUBLIC_KEY],
+    )
+    .await;
+    let user_signer =
+        LocalWallet::from_signing_key(SigningKey::from_secret_scalar(ACCOUNT_MOCK_PRIVATE_KEY));
+
+    let mut requests = vec![];
+    for _ in 0..2 {
+        let request = build_signed_outside_execution_v2(
+            user_address.0,
+            user_signer.clone(),
+            chain_id,
+            vec![
+                Call {
+                    to: vrf_account_address.0,
+                    selector: selector!("request_random"),
+                    calldata: vec![
+                        consumer_address.0,
+                        Felt::ONE, // Source::Salt
+                        cairo_short_string_to_felt("salt").unwrap(),
+                    ],
+                }
+                .into(),
+                Call {
+                    to: consumer_address.0,
+                    selector: selector!("dice_with_salt"),
+                    calldata: vec![],
+                }
+                .into(),
+            ],
+            DEFAULT_OUTSIDE_EXECUTION_EXPIRY,
+        )
+        .await;
+
+        requests.push(OutsideExecutionRequest {
+            request,
+            context: RequestContext {
+                chain_id,
+                rpc_url: Some(sequencer.url()),
+                executor: None,
+            },
+        });
+    }
+
+    let mut config = katana_config(sequencer, vrf_account_address.0, VRF_SECRET_KEY);
+    for chain in config.chains.values_mut() {
+        chain.relayers = vec![RelayerConfig {
+            address: vrf_account_address.0,
+            private_key: SecretOptions {
+                value: Some(VRF_ACCOUNT_PRIVATE_KEY.to_string()),
+                ..Default::default()
+            },
+        }];
+    }
+    let server = new_test_server_with_config(config).await;
+
+    let server = &server;
+    let status = move |id: Felt| async move {
+        server
+            .get(&format!("/status/{id:#x}"))
+            .await
+            .json::<TrackedBundle>()
+    };
+
+    // returned bundles are pending until their nonce is used
+    let result = server
+        .post("/outside_execution")
+        .json(&requests[0])
+        .await
+        .json::<OutsideExecutionResult>()
+        .result;
+    let OutsideExecution::V2(outer) = &result.outside_execution else {
+        panic!("expected a V2 outside execution");
+    };
+
+    let tracked = status(outer.nonce).await;
+    assert_eq!(tracked.status, BundleStatus::Pending);
+    assert_eq!(tracked.transaction_hash, None);
+
+    let execute_result = sequencer
+        .account(2)
+        .execute_v3(vec![result.build_execute_from_outside_call().into()])
+        .send()
+        .await
+        .unwrap();
+    TransactionWaiter::new(execute_result.transaction_hash, sequencer.provider())
+        .await
+        .unwrap();
+
+    assert_eq!(status(outer.nonce).await.status, BundleStatus::Succeeded);
+
+    // relayed bundles are also found by transaction hash
+    let relayed = server
+        .post("/outside_execution/relay")
+        .json(&requests[1])
+        .await
+        .json::<RelayResult>();
+    TransactionWaiter::new(relayed.transaction_hash, sequencer.provider())
+        .await
+        .unwrap();
+
+    let tracked = status(relayed.transaction_hash).await;
+    assert_eq!(tracked.status, BundleStatus::Succeeded);
+    assert_eq!(tracked.transaction_hash, Some(relayed.transaction_hash));
+
+    let response = server.get("/status/0x123").expect_failure().await;
+    response.assert_status_not_found();
+    assert_eq!(response.json::<ErrorBody>().code, "bundle_not_found");
+
+    let response = server.get("/status/0xzz").expect_failure().await;
+    response.assert_status_bad_request();
+    assert_eq!(response.json::<ErrorBody>().code, "invalid_bundle_id");
+}
@@ -0,0 +1,159 @@
+// Prepared StarkVRF prover and conversions between starknet felts and VRF fields.
+//
+// Only depends on external crates so the prove benchmark can include it.
+
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use ark_ec::short_weierstrass::Affine;
+use ark_ec::CurveConfig;
+use ark_ff::{BigInteger, PrimeField};
+use stark_vrf::{generate_public_key, BaseField, StarkCurve, StarkVRF};
+use starknet::core::types::Felt;
+
+pub type ScalarField = <StarkCurve as CurveConfig>::ScalarField;
+
+/// VRF secret key, its public key and the `StarkVRF` prepared for it.
+#[derive(Clone)]
+pub struct VrfKey {
+    pub secret_key: ScalarField,
+    pub public_key: Affine<StarkCurve>,
+    ecvrf: Arc<StarkVRF>,
+}
+
+/// Proof of a seed, see `submit_random` in the VRF account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VrfProof {
+    pub gamma: Affine<StarkCurve>,
+    pub c: ScalarField,
+    pub s: ScalarField,
+    pub sqrt_ratio: BaseField,
+    pub rnd: BaseField,
+}
+
+impl VrfKey {
+    /// Expects a secret key already checked to be a non zero scalar.
+    pub fn from_secret_key(secret_key: Felt) -> VrfKey {
+        let secret_key = ScalarField::from_be_bytes_mod_order(&secret_key.to_bytes_be());
+        let public_key = generate_public_key(secret_key);
+        let ecvrf = StarkVRF::new(public_key).expect("generated public key is a curve point");
+
+        VrfKey {
+            secret_key,
+            public_key,
+            ecvrf: Arc::new(ecvrf),
+        }
+    }
+
+    /// Public key as stored by the VRF account, `[x, y]`.
+    pub fn public_key_felts(&self) -> [Felt; 2] {
+        [
+            base_field_to_felt(self.public_key.x),
+            base_field_to_felt(self.public_key.y),
+        ]
+    }
+
+    pub fn ecvrf(&self) -> &StarkVRF {
+        &self.ecvrf
+    }
+
+    pub fn prove(&self, seed: &[BaseField]) -> anyhow::Result<VrfProof> {
+        let proof = self
+            .ecvrf
+            .prove(&self.secret_key, seed)
+            .map_err(|e| anyhow!("failed to prove seed: {e:?}"))?;
+        let sqrt_ratio = self.ecvrf.hash_to_sqrt_ratio_hint(seed);
+        let rnd = self
+            .ecvrf
+            .proof_to_hash(&proof)
+            .map_err(|e| anyhow!("failed to hash proof: {e:?}"))?;
+
+        Ok(VrfProof {
+            gamma: proof.0,
+            c: proof.1,
+            s: proof.2,
+            sqrt_ratio,
+            rnd,
+        })
+    }
+}
+
+impl fmt::Debug for VrfKey {
+    // the secret key is left out on purpose
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VrfKey")
+            .field("public_key", &self.public_key)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Felts and base field elements share the Stark prime, the conversion is exact.
+pub fn felt_to_base_field(felt: Felt) -> BaseField {
+    BaseField::from_be_bytes_mod_order(&felt.to_bytes_be())
+}
+
+pub fn base_field_to_felt(value: BaseField) -> Felt {
+    Felt::from_bytes_be_slice(&value.into_bigint().to_bytes_be())
+}
+
+/// The curve order is below the Stark prime so every scalar fits in a felt.
+pub fn scalar_to_felt(value: ScalarField) -> Felt {
+    Felt::from_bytes_be_slice(&value.into_bigint().to_bytes_be())
+}
+
+/// Returns `None` for felts at or above the curve order.
+pub fn felt_to_scalar(felt: Felt) -> Option<ScalarField> {
+    let value = ScalarField::from_be_bytes_mod_order(&felt.to_bytes_be());
+    (scalar_to_felt(value) == felt).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use starknet::macros::felt;
+    use std::str::FromStr;
+
+    #[test]
+    fn felt_base_field_round_trip() {
+        for felt in [Felt::ZERO, Felt::ONE, felt!("0x1a4"), Felt::MAX] {
+            let value = felt_to_base_field(felt);
+            assert_eq!(
+                value,
+                BaseField::from_str(&felt.to_biguint().to_string()).unwrap()
+            );
+            assert_eq!(base_field_to_felt(value), felt);
+        }
+    }
+
+    #[test]
+    fn felt_to_scalar_rejects_values_above_curve_order() {
+        assert_eq!(
+            felt_to_scalar(felt!("0x1a4")),
+            Some(ScalarField::from_str("420").unwrap())
+        );
+        // curve order
+        assert_eq!(
+            felt_to_scalar(felt!(
+                "0x800000000000010ffffffffffffffffb781126dcae7b2321e66a241adc64d2f"
+            )),
+            None
+        );
+        assert_eq!(felt_to_scalar(Felt::MAX), None);
+    }
+
+    #[test]
+    fn prepared_key_matches_unprepared_prove() {
+        let vrf_key = VrfKey::from_secret_key(felt!("0x1a4"));
+        assert_eq!(vrf_key.secret_key, ScalarField::from_str("420").unwrap());
+
+        let seed = [felt_to_base_field(felt!("0x1"))];
+        let proof = vrf_key.prove(&seed).unwrap();
+
+        let ecvrf = StarkVRF::new(vrf_key.public_key).unwrap();
+        let expected = ecvrf.prove(&vrf_key.secret_key, &seed).unwrap();
+        assert_eq!(proof.gamma, expected.0);
+        assert_eq!(proof.sqrt_ratio, ecvrf.hash_to_sqrt_ratio_hint(&seed));
+        assert_eq!(proof.rnd, ecvrf.proof_to_hash(&expected).unwrap());
+    }
+}